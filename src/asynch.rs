@@ -0,0 +1,544 @@
+//! Async driver built on the embedded-hal-async [`I2c`] trait.
+//!
+//! It offers the same operations as the blocking driver:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::{Address, Channel, ResetMode};
+//! use dac5578::asynch::DAC5578;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write_read(0x48, vec![0x10], vec![0x80, 0x00]),
+//! #     Transaction::write(0x48, vec![0x70, 0x00, 0x00]),
+//! #     Transaction::write(0x00, vec![0x06]),
+//! # ]);
+//! # embassy_futures::block_on(async {
+//! let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.write_and_update(Channel::A, 128).await.unwrap();
+//! assert_eq!(dac.read_dac(Channel::A).await.unwrap(), 128);
+//! dac.reset(ResetMode::Por).await.unwrap();
+//! dac.wake_up_all().await.unwrap();
+//! # dac.destroy().done();
+//! # });
+//! ```
+//!
+//! A bus shared between tasks is handed over as an `embassy-embedded-hal` [`I2cDevice`]
+//! guarded by an `embassy-sync` mutex:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::{Address, Channel};
+//! use dac5578::asynch::DAC5578;
+//! use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+//! use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//! use embassy_sync::mutex::Mutex;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write(0x4a, vec![0x31, 0x40, 0x00]),
+//! # ]);
+//! # embassy_futures::block_on(async {
+//! let bus = Mutex::<NoopRawMutex, _>::new(i2c);
+//! let mut dac = DAC5578::new(I2cDevice::new(&bus), Address::PinLow);
+//! let mut other = DAC5578::new(I2cDevice::new(&bus), Address::PinHigh);
+//! dac.write_and_update(Channel::A, 128).await.unwrap();
+//! other.write_and_update(Channel::B, 64).await.unwrap();
+//! # drop((dac, other));
+//! # bus.into_inner().done();
+//! # });
+//! ```
+//!
+//! [`I2cDevice`]: https://docs.rs/embassy-embedded-hal/latest/embassy_embedded_hal/shared_bus/asynch/i2c/struct.I2cDevice.html
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::i2c::I2c;
+
+use crate::cache::Cache;
+use crate::calibration::Calibration;
+use crate::command::{self, Commands};
+use crate::driver::Core;
+use crate::pins::{self, pin_error};
+use crate::{
+    Address, Bits10, Bits12, Bits8, Channel, ChannelSet, ClearCode, CommandType, DesiredState,
+    Error, NoPin, PowerDownMode, Register, ResetMode, Resolution,
+};
+
+/// Async DAC5578 driver (8 bit)
+pub type DAC5578<I2C, LDAC = NoPin, CLR = NoPin> = DACx578<I2C, Bits8, LDAC, CLR>;
+
+/// Async DAC6578 driver (10 bit)
+pub type DAC6578<I2C, LDAC = NoPin, CLR = NoPin> = DACx578<I2C, Bits10, LDAC, CLR>;
+
+/// Async DAC7578 driver (12 bit)
+pub type DAC7578<I2C, LDAC = NoPin, CLR = NoPin> = DACx578<I2C, Bits12, LDAC, CLR>;
+
+/// Async DACx578 driver. Wraps an async I2C port to send commands to a DAC5578, DAC6578 or DAC7578.
+/// See [`crate::DACx578`] for the blocking driver.
+#[derive(Debug)]
+pub struct DACx578<I2C, R, LDAC = NoPin, CLR = NoPin> {
+    i2c: I2C,
+    core: Core<R>,
+    ldac: LDAC,
+    clr: CLR,
+}
+
+impl<I2C, R, E> DACx578<I2C, R>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+{
+    /// Construct a new driver instance.
+    /// i2c is the initialized i2c driver port to use, address depends on the state of the ADDR0 pin (see [`Address`])
+    pub fn new(i2c: I2C, address: Address) -> Self {
+        DACx578 {
+            i2c,
+            core: Core::new(address),
+            ldac: NoPin,
+            clr: NoPin,
+        }
+    }
+}
+
+impl<I2C, R, E, LDAC, CLR> DACx578<I2C, R, LDAC, CLR>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+{
+    /// Use an output pin connected to the LDAC pin, see [`Self::pulse_ldac`].
+    /// The pin should be high, LDAC is active low.
+    pub fn with_ldac<P>(self, ldac: P) -> DACx578<I2C, R, P, CLR> {
+        DACx578 {
+            i2c: self.i2c,
+            core: self.core,
+            ldac,
+            clr: self.clr,
+        }
+    }
+
+    /// Use an output pin connected to the CLR pin, see [`Self::assert_clear`].
+    /// The pin should be high, CLR is active low.
+    pub fn with_clr<P>(self, clr: P) -> DACx578<I2C, R, LDAC, P> {
+        DACx578 {
+            i2c: self.i2c,
+            core: self.core,
+            ldac: self.ldac,
+            clr,
+        }
+    }
+
+    /// Set the voltage applied to VREFIN in microvolts, used by the voltage based methods
+    pub fn set_reference(&mut self, microvolts: u32) {
+        self.core.vref = Some(microvolts);
+    }
+
+    /// The configured reference voltage in microvolts
+    pub fn reference(&self) -> Option<u32> {
+        self.core.vref
+    }
+
+    /// Set the per-channel calibration applied to all codes written to the input registers
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.core.calibration = Some(calibration);
+    }
+
+    /// The configured calibration
+    pub fn calibration(&self) -> Option<&Calibration> {
+        self.core.calibration.as_ref()
+    }
+
+    /// Remove the calibration, returning it
+    pub fn remove_calibration(&mut self) -> Option<Calibration> {
+        self.core.calibration.take()
+    }
+
+    /// Enable the shadow register cache (see [`Cache`]), starting with all registers unknown
+    pub fn enable_cache(&mut self) {
+        self.core.cache = Some(Cache::new());
+    }
+
+    /// Disable the shadow register cache, returning it
+    pub fn disable_cache(&mut self) -> Option<Cache> {
+        self.core.cache.take()
+    }
+
+    /// The shadow register cache, if enabled
+    pub fn cache(&self) -> Option<&Cache> {
+        self.core.cache.as_ref()
+    }
+
+    /// Leave out commands that wouldn't change what the cache knows the device holds.
+    /// Enables the cache if necessary. Use [`Self::force_write`] and
+    /// [`Self::force_write_and_update`] to send a write regardless.
+    pub fn set_skip_redundant(&mut self, skip: bool) {
+        self.core.set_skip_redundant(skip);
+    }
+
+    /// Number of commands left out since the counter was last reset
+    pub fn skipped_writes(&self) -> u32 {
+        self.core.skipped_writes
+    }
+
+    /// Reset the number of commands left out
+    pub fn reset_skipped_writes(&mut self) {
+        self.core.skipped_writes = 0;
+    }
+
+    /// Write to the channel's DAC input register
+    pub async fn write(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannel, channel, data, false)
+            .await
+    }
+
+    /// Selects DAC channel to be updated
+    pub async fn update(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        let bytes = command::encode::<R>(CommandType::UpdateChannel, channel as u8, data);
+        self.send(bytes).await
+    }
+
+    /// Write to DAC input register for a channel and update channel DAC register
+    pub async fn write_and_update(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannelAndUpdate, channel, data, false)
+            .await
+    }
+
+    /// Write to the channel's DAC input register, even if the cache knows it holds the code
+    pub async fn force_write(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannel, channel, data, true)
+            .await
+    }
+
+    /// Write to DAC input register for a channel and update channel DAC register,
+    /// even if the cache knows the channel already outputs the code
+    pub async fn force_write_and_update(
+        &mut self,
+        channel: Channel,
+        data: u16,
+    ) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannelAndUpdate, channel, data, true)
+            .await
+    }
+
+    /// Write to Selected DAC Input Register and Update All DAC Registers (Global Software LDAC)
+    pub async fn write_and_update_all(
+        &mut self,
+        channel: Channel,
+        data: u16,
+    ) -> Result<(), Error<E>> {
+        self.write_code(
+            CommandType::WriteToChannelAndUpdateAll,
+            channel,
+            data,
+            false,
+        )
+        .await
+    }
+
+    /// Write each channel of the set to its DAC input register and update all DAC registers
+    /// with the last write, so all outputs change together.
+    /// `codes` holds the codes for channels A to H, codes of channels outside the set are ignored.
+    /// All codes of the set are checked before anything is sent.
+    pub async fn write_and_update_channels<C>(
+        &mut self,
+        channels: C,
+        codes: &[u16; 8],
+    ) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        let commands = self.core.write_channels(
+            channels.into(),
+            codes,
+            CommandType::WriteToChannelAndUpdateAll,
+        )?;
+        self.send_all(&commands, false).await
+    }
+
+    /// Move the device to the desired state with as few commands as possible,
+    /// returning the number of commands sent.
+    ///
+    /// The target is compared with the shadow register cache, which is enabled if necessary.
+    /// Registers the cache doesn't know are written, unless latching several outputs together
+    /// requires knowing the outputs that aren't targeted; those are read back first.
+    /// All target codes are checked before anything is sent.
+    pub async fn apply(&mut self, state: &DesiredState) -> Result<usize, Error<E>> {
+        for channel in self.core.unknown(state)?.iter() {
+            self.read_input(channel).await?;
+            self.read_dac(channel).await?;
+        }
+        let commands = self.core.plan(state);
+        self.send_all(&commands, false).await?;
+        Ok(commands.as_slice().len())
+    }
+
+    /// Write to DAC input register for a channel and update channel DAC register,
+    /// then read the DAC register back to confirm the device holds the code.
+    /// Fails with [`Error::ReadbackMismatch`] if it doesn't and with
+    /// [`Error::InvalidChannel`] for [`Channel::All`].
+    pub async fn write_and_update_verified(
+        &mut self,
+        channel: Channel,
+        data: u16,
+    ) -> Result<(), Error<E>> {
+        let expected = self.core.expected(channel, data)?;
+        self.write_and_update(channel, data).await?;
+        let actual = self.read_dac(channel).await?;
+        if actual != expected {
+            return Err(Error::ReadbackMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Set the channel's output to the given voltage in microvolts.
+    /// Returns the voltage actually set after quantization to the part's resolution.
+    /// Fails with [`Error::NoReference`] if no reference voltage was configured
+    /// with [`Self::set_reference`].
+    pub async fn write_and_update_microvolts(
+        &mut self,
+        channel: Channel,
+        microvolts: u32,
+    ) -> Result<u32, Error<E>> {
+        let (code, actual) = self.core.microvolts(microvolts)?;
+        self.write_and_update(channel, code).await?;
+        Ok(actual)
+    }
+
+    /// Set the channel's output to the given voltage.
+    /// Returns the voltage actually set after quantization to the part's resolution.
+    /// Fails with [`Error::NoReference`] if no reference voltage was configured
+    /// with [`Self::set_reference`].
+    pub async fn write_and_update_volts(
+        &mut self,
+        channel: Channel,
+        volts: f32,
+    ) -> Result<f32, Error<E>> {
+        let microvolts = self
+            .write_and_update_microvolts(channel, (volts * 1e6) as u32)
+            .await?;
+        Ok(microvolts as f32 / 1e6)
+    }
+
+    /// Perform a software reset using the selected mode
+    pub async fn reset(&mut self, mode: ResetMode) -> Result<(), Error<E>> {
+        self.send(command::reset(mode)).await
+    }
+
+    /// Whether the device was put into High-Speed mode, as tracked by the driver across resets
+    pub fn is_high_speed(&self) -> bool {
+        self.core.high_speed
+    }
+
+    /// Start a High-Speed (3.4 MHz) session.
+    ///
+    /// Sends the software reset that puts the device into High-Speed mode and keeps it there
+    /// across STOP conditions. Once this returns, the I2C controller can be switched to its Hs
+    /// clock; the Hs master code in front of every Hs transfer is the controller's job, as a
+    /// master code sent on its own ends with a STOP and returns the bus to F/S mode.
+    /// Resets the device registers. If the bus fails, the device is left in F/S mode.
+    pub async fn enter_high_speed(&mut self) -> Result<(), Error<E>> {
+        self.reset(ResetMode::SetHighSpeed).await
+    }
+
+    /// End a High-Speed session with a software reset that returns the device to F/S mode.
+    /// Switch the I2C controller back to F/S speed before calling this.
+    /// Resets the device registers, use [`ResetMode::MaintainHighSpeed`] to reset without
+    /// leaving High-Speed mode.
+    pub async fn leave_high_speed(&mut self) -> Result<(), Error<E>> {
+        self.reset(ResetMode::Por).await
+    }
+
+    /// Power down the given channels, leaving their outputs in the selected mode
+    pub async fn power_down<C>(&mut self, channels: C, mode: PowerDownMode) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        let bytes = command::power(channels.into(), mode as u8);
+        self.send(bytes).await
+    }
+
+    /// Power up the given channels
+    pub async fn power_up<C>(&mut self, channels: C) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        let bytes = command::power(channels.into(), command::POWER_UP);
+        self.send(bytes).await
+    }
+
+    /// Set the code the outputs are cleared to when the CLR pin is asserted
+    pub async fn set_clear_code(&mut self, code: ClearCode) -> Result<(), Error<E>> {
+        self.send(command::clear_code(code)).await
+    }
+
+    /// Set which channels ignore the hardware LDAC pin.
+    /// Masked channels are only updated by software, all other channels latch on the LDAC pin.
+    pub async fn set_ldac_mask<C>(&mut self, channels: C) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        let bytes = command::ldac(channels.into());
+        self.send(bytes).await
+    }
+
+    /// Read back the channel's DAC input register
+    pub async fn read_input(&mut self, channel: Channel) -> Result<u16, Error<E>> {
+        self.read_register(Register::Input, channel).await
+    }
+
+    /// Read back the channel's DAC register
+    pub async fn read_dac(&mut self, channel: Channel) -> Result<u16, Error<E>> {
+        self.read_register(Register::Dac, channel).await
+    }
+
+    /// Read back a register for a channel.
+    /// Sends the command byte followed by a repeated-start read of the two data bytes.
+    /// Fails with [`Error::InvalidChannel`] for [`Channel::All`].
+    pub async fn read_register(
+        &mut self,
+        register: Register,
+        channel: Channel,
+    ) -> Result<u16, Error<E>> {
+        let bytes = self.read_raw(self.core.read(register, channel)?).await?;
+        Ok(self.core.register_read(register, channel, bytes))
+    }
+
+    /// Read back the code the outputs are cleared to when the CLR pin is asserted
+    pub async fn read_clear_code(&mut self) -> Result<ClearCode, Error<E>> {
+        let bytes = self.read_raw(CommandType::ClearCode as u8).await?;
+        Ok(self.core.clear_code_read(bytes))
+    }
+
+    /// Read back the LDAC register, i.e. the channels that ignore the hardware LDAC pin
+    pub async fn read_ldac_mask(&mut self) -> Result<ChannelSet, Error<E>> {
+        let bytes = self.read_raw(CommandType::Ldac as u8).await?;
+        Ok(self.core.ldac_mask_read(bytes))
+    }
+
+    /// Send a wake-up command over the I2C bus.
+    /// WARNING: This is a general call command and can wake-up other devices on the bus as well.
+    pub async fn wake_up_all(&mut self) -> Result<(), Error<E>> {
+        self.send_general_call(&command::WAKE_UP).await
+    }
+
+    /// Send a reset command on the I2C bus.
+    /// WARNING: This is a general call command and can reset other devices on the bus as well.
+    pub async fn reset_all(&mut self) -> Result<(), Error<E>> {
+        self.send_general_call(&command::RESET).await
+    }
+
+    /// Destroy the driver, return the wrapped I2C
+    pub fn destroy(self) -> I2C {
+        self.i2c
+    }
+
+    /// Destroy the driver, return the wrapped I2C and the LDAC and CLR pins
+    pub fn release(self) -> (I2C, LDAC, CLR) {
+        (self.i2c, self.ldac, self.clr)
+    }
+
+    /// Send a command writing a code to the channel's input register, applying the calibration.
+    /// Fails with [`Error::CodeOutOfRange`] for codes above the maximum of the part.
+    async fn write_code(
+        &mut self,
+        command: CommandType,
+        channel: Channel,
+        code: u16,
+        force: bool,
+    ) -> Result<(), Error<E>> {
+        let commands = self.core.write(command, channel, code)?;
+        self.send_all(&commands, force).await
+    }
+
+    /// Send a sequence of three byte commands to the device
+    async fn send_all(&mut self, commands: &Commands, force: bool) -> Result<(), Error<E>> {
+        for bytes in commands.as_slice() {
+            self.transmit(*bytes, force).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a three byte command to the device unless it is redundant
+    async fn send(&mut self, bytes: [u8; 3]) -> Result<(), Error<E>> {
+        self.transmit(bytes, false).await
+    }
+
+    /// Send a three byte command to the device and record it in the cache.
+    /// Unless forced, redundant commands are left out if enabled with [`Self::set_skip_redundant`].
+    async fn transmit(&mut self, bytes: [u8; 3], force: bool) -> Result<(), Error<E>> {
+        if self.core.skip(&bytes, force) {
+            return Ok(());
+        }
+        self.i2c
+            .write(self.core.address, &bytes)
+            .await
+            .map_err(Error::I2c)?;
+        self.core.sent(&bytes);
+        Ok(())
+    }
+
+    /// Send a general call command and record it in the cache
+    async fn send_general_call(&mut self, bytes: &[u8; 1]) -> Result<(), Error<E>> {
+        self.i2c
+            .write(command::GENERAL_CALL_ADDRESS, bytes)
+            .await
+            .map_err(Error::I2c)?;
+        self.core.general_call_sent(bytes);
+        Ok(())
+    }
+
+    /// Send the command byte and read the two data bytes after a repeated start
+    async fn read_raw(&mut self, command: u8) -> Result<[u8; 2], Error<E>> {
+        let mut buffer = [0u8; 2];
+        self.i2c
+            .write_read(self.core.address, &[command], &mut buffer)
+            .await
+            .map_err(Error::I2c)?;
+        Ok(buffer)
+    }
+}
+
+impl<I2C, R, E, LDAC, CLR> DACx578<I2C, R, LDAC, CLR>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+    LDAC: OutputPin,
+{
+    /// Pulse the LDAC pin, latching the input registers of all channels that aren't masked
+    /// with [`Self::set_ldac_mask`] into their DAC registers at once
+    pub fn pulse_ldac(&mut self) -> Result<(), Error<E>> {
+        pins::pulse_ldac(&mut self.ldac, &mut self.core)
+    }
+
+    /// Write each channel of the set to its DAC input register, then pulse the LDAC pin
+    /// so all outputs change together without a software update command.
+    /// `codes` holds the codes for channels A to H, codes of channels outside the set are ignored.
+    /// All codes of the set are checked before anything is sent.
+    pub async fn write_and_latch_channels<C>(
+        &mut self,
+        channels: C,
+        codes: &[u16; 8],
+    ) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        let commands =
+            self.core
+                .write_channels(channels.into(), codes, CommandType::WriteToChannel)?;
+        self.send_all(&commands, false).await?;
+        self.pulse_ldac()
+    }
+}
+
+impl<I2C, R, E, LDAC, CLR> DACx578<I2C, R, LDAC, CLR>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+    CLR: OutputPin,
+{
+    /// Assert the CLR pin, loading the clear code (see [`Self::set_clear_code`]) into the
+    /// input and DAC registers of all channels
+    pub fn assert_clear(&mut self) -> Result<(), Error<E>> {
+        pins::assert_clear(&mut self.clr, &mut self.core)
+    }
+
+    /// Release the CLR pin
+    pub fn release_clear(&mut self) -> Result<(), Error<E>> {
+        self.clr.set_high().map_err(pin_error)
+    }
+}
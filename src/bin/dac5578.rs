@@ -0,0 +1,290 @@
+//! Command line tool to drive a DACx578 over Linux i2c-dev, or against the simulator with `--sim`.
+
+use std::fmt::Debug;
+use std::thread::sleep;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use dac5578::sim::Simulator;
+use dac5578::*;
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::I2cdev;
+
+#[derive(Parser)]
+#[command(version, about = "Drive a TI DAC5578/DAC6578/DAC7578 over I2C")]
+struct Cli {
+    /// I2C bus device
+    #[arg(long, default_value = "/dev/i2c-1")]
+    bus: String,
+    /// State of the ADDR0 pin
+    #[arg(long, value_enum, default_value = "low")]
+    address: AddressArg,
+    /// Resolution of the part: 8 (DAC5578), 10 (DAC6578) or 12 (DAC7578)
+    #[arg(long, default_value = "8", value_parser = ["8", "10", "12"])]
+    bits: String,
+    /// Run against an in-process simulator instead of the I2C bus
+    #[arg(long)]
+    sim: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a code or voltage to a channel and update its output
+    Set {
+        /// Channel A..H or "all"
+        channel: String,
+        /// Code in the part's resolution
+        #[arg(required_unless_present = "volts")]
+        code: Option<u16>,
+        /// Output voltage instead of a code, requires --vref
+        #[arg(long, requires = "vref", conflicts_with = "code")]
+        volts: Option<f32>,
+        /// Reference voltage applied to VREFIN
+        #[arg(long)]
+        vref: Option<f32>,
+    },
+    /// Read back a channel's input and DAC register
+    Read {
+        /// Channel A..H
+        channel: String,
+    },
+    /// Perform a software reset
+    Reset {
+        #[arg(value_enum, default_value = "por")]
+        mode: ResetArg,
+    },
+    /// Power down channels
+    PowerDown {
+        /// Comma separated channels, e.g. "A,B" or "all"
+        channels: String,
+        #[arg(value_enum)]
+        mode: PowerDownArg,
+    },
+    /// Power up channels
+    PowerUp {
+        /// Comma separated channels, e.g. "A,B" or "all"
+        channels: String,
+    },
+    /// Set or read the code the outputs are cleared to when CLR is asserted
+    ClearCode {
+        /// Clear code to set, reads it back if omitted
+        #[arg(value_enum)]
+        code: Option<ClearCodeArg>,
+    },
+    /// Set or read the channels that ignore the LDAC pin
+    Ldac {
+        /// Comma separated channels, e.g. "A,B", "all" or "none", reads the mask back if omitted
+        channels: Option<String>,
+    },
+    /// Send the wake-up general call
+    WakeAll,
+    /// Send the reset general call
+    ResetAll,
+    /// Step a channel from one code to another
+    Sweep {
+        /// Channel A..H or "all"
+        channel: String,
+        from: u16,
+        to: u16,
+        /// Code increment per step
+        #[arg(long, default_value = "1")]
+        step: u16,
+        /// Delay between steps in milliseconds
+        #[arg(long, default_value = "10")]
+        delay_ms: u64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AddressArg {
+    Low,
+    High,
+    Float,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ResetArg {
+    Por,
+    SetHighSpeed,
+    MaintainHighSpeed,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PowerDownArg {
+    #[value(name = "1k")]
+    Pulldown1K,
+    #[value(name = "100k")]
+    Pulldown100K,
+    #[value(name = "hi-z")]
+    HighImpedance,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ClearCodeArg {
+    Zero,
+    Mid,
+    Full,
+    Ignore,
+}
+
+fn to_address(address: AddressArg) -> Address {
+    match address {
+        AddressArg::Low => Address::PinLow,
+        AddressArg::High => Address::PinHigh,
+        AddressArg::Float => Address::PinFloat,
+    }
+}
+
+fn parse_channel(channel: &str) -> Result<Channel, String> {
+    if channel.eq_ignore_ascii_case("all") {
+        return Ok(Channel::All);
+    }
+    channel
+        .parse()
+        .map_err(|_| format!("unknown channel {}", channel))
+}
+
+fn parse_channels(channels: &str) -> Result<ChannelSet, String> {
+    if channels.eq_ignore_ascii_case("none") {
+        return Ok(ChannelSet::EMPTY);
+    }
+    channels
+        .split(',')
+        .map(|c| parse_channel(c.trim()))
+        .collect()
+}
+
+fn run<I2C, R>(dac: &mut DACx578<I2C, R>, command: Command) -> Result<(), String>
+where
+    I2C: I2c,
+    I2C::Error: Debug,
+    R: Resolution,
+{
+    let bus = |error: Error<I2C::Error>| format!("{:?}", error);
+    match command {
+        Command::Set {
+            channel,
+            code,
+            volts,
+            vref,
+        } => {
+            let channel = parse_channel(&channel)?;
+            match (code, volts, vref) {
+                (_, Some(volts), Some(vref)) => {
+                    dac.set_reference((vref * 1e6) as u32);
+                    let volts = dac.write_and_update_volts(channel, volts).map_err(bus)?;
+                    println!("set {:.6} V", volts);
+                }
+                (Some(code), _, _) => dac.write_and_update(channel, code).map_err(bus)?,
+                _ => return Err("either a code or --volts is required".into()),
+            }
+        }
+        Command::Read { channel } => {
+            let channel = parse_channel(&channel)?;
+            let input = dac.read_input(channel).map_err(bus)?;
+            let output = dac.read_dac(channel).map_err(bus)?;
+            println!("input: {}", input);
+            println!("dac:   {}", output);
+        }
+        Command::Reset { mode } => {
+            let mode = match mode {
+                ResetArg::Por => ResetMode::Por,
+                ResetArg::SetHighSpeed => ResetMode::SetHighSpeed,
+                ResetArg::MaintainHighSpeed => ResetMode::MaintainHighSpeed,
+            };
+            dac.reset(mode).map_err(bus)?;
+        }
+        Command::PowerDown { channels, mode } => {
+            let mode = match mode {
+                PowerDownArg::Pulldown1K => PowerDownMode::Pulldown1K,
+                PowerDownArg::Pulldown100K => PowerDownMode::Pulldown100K,
+                PowerDownArg::HighImpedance => PowerDownMode::HighImpedance,
+            };
+            dac.power_down(parse_channels(&channels)?, mode)
+                .map_err(bus)?;
+        }
+        Command::PowerUp { channels } => dac.power_up(parse_channels(&channels)?).map_err(bus)?,
+        Command::ClearCode { code: Some(code) } => {
+            let code = match code {
+                ClearCodeArg::Zero => ClearCode::ZeroScale,
+                ClearCodeArg::Mid => ClearCode::MidScale,
+                ClearCodeArg::Full => ClearCode::FullScale,
+                ClearCodeArg::Ignore => ClearCode::Ignore,
+            };
+            dac.set_clear_code(code).map_err(bus)?;
+        }
+        Command::ClearCode { code: None } => {
+            println!("{:?}", dac.read_clear_code().map_err(bus)?)
+        }
+        Command::Ldac {
+            channels: Some(channels),
+        } => dac.set_ldac_mask(parse_channels(&channels)?).map_err(bus)?,
+        Command::Ldac { channels: None } => {
+            println!("{:08b}", dac.read_ldac_mask().map_err(bus)?.bits())
+        }
+        Command::WakeAll => dac.wake_up_all().map_err(bus)?,
+        Command::ResetAll => dac.reset_all().map_err(bus)?,
+        Command::Sweep {
+            channel,
+            from,
+            to,
+            step,
+            delay_ms,
+        } => {
+            if from.max(to) > R::MAX_CODE {
+                return Err(format!("code exceeds {}", R::MAX_CODE));
+            }
+            let step = step.max(1) as usize;
+            let codes: Vec<u16> = if from <= to {
+                (from..=to).step_by(step).collect()
+            } else {
+                (to..=from).rev().step_by(step).collect()
+            };
+            let channel = parse_channel(&channel)?;
+            for code in codes {
+                dac.write_and_update(channel, code).map_err(bus)?;
+                sleep(Duration::from_millis(delay_ms));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_with<I2C>(i2c: I2C, cli: Cli) -> Result<(), String>
+where
+    I2C: I2c,
+    I2C::Error: Debug,
+{
+    let address = to_address(cli.address);
+    match cli.bits.as_str() {
+        "10" => run(&mut DAC6578::new(i2c, address), cli.command),
+        "12" => run(&mut DAC7578::new(i2c, address), cli.command),
+        _ => run(&mut DAC5578::new(i2c, address), cli.command),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let result = if cli.sim {
+        let sim = Simulator::new();
+        let address = cli.address;
+        match cli.bits.as_str() {
+            "10" => sim.add_device::<Bits10>(to_address(address)),
+            "12" => sim.add_device::<Bits12>(to_address(address)),
+            _ => sim.add_device::<Bits8>(to_address(address)),
+        }
+        run_with(sim.clone(), cli).map(|()| {
+            println!("{:#?}", sim.device(to_address(address)).unwrap());
+        })
+    } else {
+        I2cdev::new(&cli.bus)
+            .map_err(|error| format!("failed to open {}: {}", cli.bus, error))
+            .and_then(|i2c| run_with(i2c, cli))
+    };
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,252 @@
+//! Shadow copy of the device registers, kept up to date by the driver.
+//!
+//! The cache records every command the driver sends and every register it reads back,
+//! following the update semantics of the device. Registers the driver hasn't written or
+//! read since the cache was enabled are unknown. A software or general call reset restores
+//! the power-on state.
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x02, 0x80, 0x00]),
+//! #     Transaction::write(0x48, vec![0x20, 0x40, 0x00]),
+//! #     Transaction::write(0x48, vec![0x70, 0x00, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.enable_cache();
+//! dac.write(Channel::C, 128).unwrap();
+//! assert_eq!(dac.cache().unwrap().input(Channel::C), Some(128));
+//! assert_eq!(dac.cache().unwrap().dac(Channel::C), None);
+//!
+//! // Updates all DAC registers (global software LDAC)
+//! dac.write_and_update_all(Channel::A, 64).unwrap();
+//! assert_eq!(dac.cache().unwrap().dac(Channel::C), Some(128));
+//! assert_eq!(dac.cache().unwrap().dac(Channel::A), Some(64));
+//! assert_eq!(dac.cache().unwrap().dac(Channel::B), None);
+//! assert_eq!(dac.cache().unwrap().dac(Channel::All), None);
+//!
+//! dac.reset(ResetMode::Por).unwrap();
+//! assert_eq!(dac.cache().unwrap().dac(Channel::C), Some(0));
+//! # dac.destroy().done();
+//! ```
+//!
+//! On top of the cache the driver can leave out commands that wouldn't change the device
+//! state, e.g. when a control loop keeps writing the same code. Forced writes are always sent:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write(0x48, vec![0x40, 0x7f, 0xe0]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.set_skip_redundant(true);
+//! dac.write_and_update(Channel::A, 128).unwrap();
+//! dac.write_and_update(Channel::A, 128).unwrap();
+//! dac.force_write_and_update(Channel::A, 128).unwrap();
+//! assert_eq!(dac.skipped_writes(), 1);
+//!
+//! dac.power_down(ChannelSet::ALL, PowerDownMode::HighImpedance).unwrap();
+//! dac.power_down(Channel::B, PowerDownMode::HighImpedance).unwrap();
+//! assert_eq!(dac.skipped_writes(), 2);
+//! # dac.destroy().done();
+//! ```
+
+use crate::{command, Channel, ChannelSet, ClearCode, PowerDownMode, PowerState, Resolution};
+
+/// Last known state of the device registers, `None` where unknown
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Cache {
+    input: [Option<u16>; 8],
+    dac: [Option<u16>; 8],
+    power: [Option<PowerState>; 8],
+    clear_code: Option<ClearCode>,
+    ldac_mask: Option<ChannelSet>,
+}
+
+impl Cache {
+    /// Create a cache with all registers unknown
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a cache holding the power-on state of the device
+    pub fn power_on() -> Self {
+        Cache {
+            input: [Some(0); 8],
+            dac: [Some(0); 8],
+            power: [Some(PowerState::Up); 8],
+            clear_code: Some(ClearCode::ZeroScale),
+            ldac_mask: Some(ChannelSet::EMPTY),
+        }
+    }
+
+    /// Forget all registers
+    pub fn invalidate(&mut self) {
+        *self = Cache::new();
+    }
+
+    /// Last known code of the channel's DAC input register, `None` for [`Channel::All`]
+    pub fn input(&self, channel: Channel) -> Option<u16> {
+        self.input[channel.index()?]
+    }
+
+    /// Last known code of the channel's DAC register, i.e. the code driving the output.
+    /// `None` for [`Channel::All`].
+    pub fn dac(&self, channel: Channel) -> Option<u16> {
+        self.dac[channel.index()?]
+    }
+
+    /// Last known power state of the channel, `None` for [`Channel::All`]
+    pub fn power(&self, channel: Channel) -> Option<PowerState> {
+        self.power[channel.index()?]
+    }
+
+    /// Last known code the outputs are cleared to when the CLR pin is asserted
+    pub fn clear_code(&self) -> Option<ClearCode> {
+        self.clear_code
+    }
+
+    /// Last known set of channels that ignore the hardware LDAC pin
+    pub fn ldac_mask(&self) -> Option<ChannelSet> {
+        self.ldac_mask
+    }
+
+    /// Record a three byte command sent to the device
+    pub(crate) fn record<R: Resolution>(&mut self, bytes: &[u8; 3]) {
+        let channels = match bytes[0] & 0x0f {
+            access @ 0..=7 => ChannelSet::from_bits(1 << access),
+            0x0f => ChannelSet::ALL,
+            _ => ChannelSet::EMPTY,
+        };
+        let code = command::decode::<R>([bytes[1], bytes[2]]);
+
+        match bytes[0] & 0xf0 {
+            0x00 => channels
+                .iter()
+                .for_each(|c| self.input[c as usize] = Some(code)),
+            0x10 => channels
+                .iter()
+                .for_each(|c| self.dac[c as usize] = self.input[c as usize]),
+            0x20 => {
+                channels
+                    .iter()
+                    .for_each(|c| self.input[c as usize] = Some(code));
+                self.dac = self.input;
+            }
+            0x30 => channels.iter().for_each(|c| {
+                self.input[c as usize] = Some(code);
+                self.dac[c as usize] = Some(code);
+            }),
+            0x40 => {
+                let value = u16::from_be_bytes([bytes[1], bytes[2]]);
+                let state = match value >> 13 & 0b11 {
+                    0b01 => PowerState::Down(PowerDownMode::Pulldown1K),
+                    0b10 => PowerState::Down(PowerDownMode::Pulldown100K),
+                    0b11 => PowerState::Down(PowerDownMode::HighImpedance),
+                    _ => PowerState::Up,
+                };
+                ChannelSet::from_bits((value >> 5) as u8)
+                    .iter()
+                    .for_each(|c| self.power[c as usize] = Some(state));
+            }
+            0x50 => self.clear_code = Some(command::decode_clear_code([bytes[1], bytes[2]])),
+            0x60 => self.ldac_mask = Some(command::decode_ldac([bytes[1], bytes[2]])),
+            0x70 => *self = Cache::power_on(),
+            _ => {}
+        }
+    }
+
+    /// Whether sending the three byte command would leave the known device state unchanged
+    pub(crate) fn is_redundant<R: Resolution>(&self, bytes: &[u8; 3]) -> bool {
+        let channels = match bytes[0] & 0x0f {
+            access @ 0..=7 => ChannelSet::from_bits(1 << access),
+            0x0f => ChannelSet::ALL,
+            _ => return false,
+        };
+        let code = Some(command::decode::<R>([bytes[1], bytes[2]]));
+        let latched = |c: Channel| {
+            self.dac[c as usize].is_some() && self.dac[c as usize] == self.input[c as usize]
+        };
+
+        match bytes[0] & 0xf0 {
+            0x00 => channels.iter().all(|c| self.input[c as usize] == code),
+            0x10 => channels.iter().all(latched),
+            0x20 => {
+                channels.iter().all(|c| self.input[c as usize] == code)
+                    && Channel::iter().all(latched)
+            }
+            0x30 => channels
+                .iter()
+                .all(|c| self.input[c as usize] == code && self.dac[c as usize] == code),
+            _ => {
+                let mut cache = self.clone();
+                cache.record::<R>(bytes);
+                bytes[0] & 0xf0 != 0x70 && cache == *self
+            }
+        }
+    }
+
+    /// Record a pulse of the LDAC pin, which latches the input registers of all channels
+    /// that aren't masked. With the mask unknown, outputs that might change become unknown.
+    pub(crate) fn record_ldac_pulse(&mut self) {
+        for c in Channel::iter() {
+            let i = c as usize;
+            match self.ldac_mask {
+                Some(mask) if mask.contains(c) => {}
+                Some(_) => self.dac[i] = self.input[i],
+                None if self.dac[i] == self.input[i] => {}
+                None => self.dac[i] = None,
+            }
+        }
+    }
+
+    /// Record the CLR pin being asserted, which loads the clear code into the input and DAC
+    /// registers unless the clear code is [`ClearCode::Ignore`]
+    pub(crate) fn record_clear<R: Resolution>(&mut self) {
+        let code = match self.clear_code {
+            Some(ClearCode::Ignore) => return,
+            Some(ClearCode::ZeroScale) => Some(0),
+            Some(ClearCode::MidScale) => Some(1 << (R::BITS - 1)),
+            Some(ClearCode::FullScale) => Some(R::MAX_CODE),
+            None => None,
+        };
+        self.input = [code; 8];
+        self.dac = [code; 8];
+    }
+
+    /// Record a general call sent on the bus
+    pub(crate) fn record_general_call(&mut self, bytes: &[u8; 1]) {
+        if *bytes == command::WAKE_UP {
+            self.power = [Some(PowerState::Up); 8];
+        } else if *bytes == command::RESET {
+            *self = Cache::power_on();
+        }
+    }
+
+    /// Record a channel's DAC input register read back from the device
+    pub(crate) fn record_input(&mut self, channel: Channel, code: u16) {
+        if let Some(index) = channel.index() {
+            self.input[index] = Some(code);
+        }
+    }
+
+    /// Record a channel's DAC register read back from the device
+    pub(crate) fn record_dac(&mut self, channel: Channel, code: u16) {
+        if let Some(index) = channel.index() {
+            self.dac[index] = Some(code);
+        }
+    }
+
+    /// Record the clear code read back from the device
+    pub(crate) fn record_clear_code(&mut self, code: ClearCode) {
+        self.clear_code = Some(code);
+    }
+
+    /// Record the LDAC mask read back from the device
+    pub(crate) fn record_ldac_mask(&mut self, channels: ChannelSet) {
+        self.ldac_mask = Some(channels);
+    }
+}
@@ -0,0 +1,281 @@
+//! Per-channel calibration applied by the driver before a code is sent to the device.
+//!
+//! Each channel has a gain and an offset and an optional piecewise-linear correction table.
+//! The table is applied to the requested code first, the result is then scaled by the gain,
+//! shifted by the offset and clamped to the resolution of the part.
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use dac5578::calibration::{Calibration, ChannelCalibration};
+//! # let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x30, 0x89, 0x00])]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! let mut calibration = Calibration::new();
+//! // Compensate an output stage on channel A with a gain of 0.9 and an offset of 5 codes
+//! calibration.set_channel(Channel::A, ChannelCalibration::new(72818, -5));
+//! dac.set_calibration(calibration);
+//! dac.write_and_update(Channel::A, 128).unwrap();
+//! # dac.destroy().done();
+//! ```
+//!
+//! Calibrations serialize into a compact, versioned format protected by a CRC so they can be
+//! stored in an EEPROM and loaded at boot:
+//! ```
+//! # use dac5578::Channel;
+//! use dac5578::calibration::{Calibration, ChannelCalibration, MAX_SERIALIZED_LEN};
+//! let mut calibration = Calibration::new();
+//! let mut channel = ChannelCalibration::new(65536, 3);
+//! channel.set_table(&[(0, 0), (128, 130), (255, 255)]).unwrap();
+//! assert_eq!(channel.correct(64, 255), 68);
+//! calibration.set_channel(Channel::C, channel);
+//!
+//! let mut buffer = [0u8; MAX_SERIALIZED_LEN];
+//! let len = calibration.serialize(&mut buffer).unwrap();
+//! assert_eq!(Calibration::deserialize(&buffer[..len]).unwrap(), calibration);
+//! # buffer[3] ^= 0x01;
+//! # assert_eq!(Calibration::deserialize(&buffer[..len]), Err(dac5578::calibration::CalibrationError::CrcMismatch));
+//! ```
+
+use crate::Channel;
+
+/// Maximum number of points of a channel's correction table
+pub const MAX_POINTS: usize = 8;
+
+/// Version of the serialization format written by [`Calibration::serialize`]
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Maximum length of a serialized [`Calibration`] in bytes
+pub const MAX_SERIALIZED_LEN: usize = 1 + 8 * (CHANNEL_HEADER_LEN + MAX_POINTS * 4) + 2;
+
+/// Gain of 1.0 in the Q16.16 fixed-point format used by [`ChannelCalibration`]
+pub const UNITY_GAIN: i32 = 1 << 16;
+
+/// Serialized length of a channel's gain, offset and table length
+const CHANNEL_HEADER_LEN: usize = 4 + 2 + 1;
+
+/// Errors of building, serializing or deserializing a calibration
+#[derive(Debug, PartialEq)]
+pub enum CalibrationError {
+    /// The buffer is too small to hold the serialized calibration
+    BufferTooSmall,
+    /// The data ends before the calibration is complete
+    Truncated,
+    /// The data was written in an unsupported format version
+    UnsupportedVersion(u8),
+    /// The CRC of the data doesn't match
+    CrcMismatch,
+    /// A correction table has too many points or inputs that are not strictly increasing
+    InvalidTable,
+}
+
+/// Calibration of a single channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelCalibration {
+    gain: i32,
+    offset: i16,
+    points: [(u16, u16); MAX_POINTS],
+    len: u8,
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        ChannelCalibration::new(UNITY_GAIN, 0)
+    }
+}
+
+impl ChannelCalibration {
+    /// Create a calibration from a gain in Q16.16 fixed-point (see [`UNITY_GAIN`])
+    /// and an offset in codes, without a correction table
+    pub fn new(gain: i32, offset: i16) -> Self {
+        ChannelCalibration {
+            gain,
+            offset,
+            points: [(0, 0); MAX_POINTS],
+            len: 0,
+        }
+    }
+
+    /// Gain in Q16.16 fixed-point
+    pub fn gain(&self) -> i32 {
+        self.gain
+    }
+
+    /// Offset in codes
+    pub fn offset(&self) -> i16 {
+        self.offset
+    }
+
+    /// Points of the correction table as `(requested code, corrected code)`
+    pub fn table(&self) -> &[(u16, u16)] {
+        &self.points[..self.len as usize]
+    }
+
+    /// Set the piecewise-linear correction table as `(requested code, corrected code)` points.
+    /// The requested codes must be strictly increasing. An empty table disables the correction.
+    pub fn set_table(&mut self, points: &[(u16, u16)]) -> Result<(), CalibrationError> {
+        if points.len() > MAX_POINTS || points.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(CalibrationError::InvalidTable);
+        }
+        self.points[..points.len()].copy_from_slice(points);
+        self.len = points.len() as u8;
+        Ok(())
+    }
+
+    /// Apply the calibration to a code, clamping the result to `0..=max_code`
+    pub fn correct(&self, code: u16, max_code: u16) -> u16 {
+        let code = self.interpolate(code);
+        let scaled = ((code * self.gain as i64 + (1 << 15)) >> 16) + self.offset as i64;
+        scaled.clamp(0, max_code as i64) as u16
+    }
+
+    /// Look up the code in the correction table, extrapolating beyond its first and last segment
+    fn interpolate(&self, code: u16) -> i64 {
+        let table = self.table();
+        let code = code as i64;
+        match table.len() {
+            0 => code,
+            1 => code + table[0].1 as i64 - table[0].0 as i64,
+            len => {
+                let segment = table[1..len - 1]
+                    .iter()
+                    .take_while(|point| code > point.0 as i64)
+                    .count();
+                let (x0, y0) = table[segment];
+                let (x1, y1) = table[segment + 1];
+                let (x0, y0, x1, y1) = (x0 as i64, y0 as i64, x1 as i64, y1 as i64);
+                y0 + (code - x0) * (y1 - y0) / (x1 - x0)
+            }
+        }
+    }
+}
+
+/// Calibration of all eight channels
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Calibration {
+    channels: [ChannelCalibration; 8],
+}
+
+impl Calibration {
+    /// Create a calibration that leaves all codes unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calibration of a channel, `None` for [`Channel::All`]
+    pub fn channel(&self, channel: Channel) -> Option<&ChannelCalibration> {
+        self.channels.get(channel.index()?)
+    }
+
+    /// Set the calibration of a channel. [`Channel::All`] sets all channels.
+    pub fn set_channel(&mut self, channel: Channel, calibration: ChannelCalibration) {
+        match channel.index() {
+            Some(index) => self.channels[index] = calibration,
+            None => self.channels = [calibration; 8],
+        }
+    }
+
+    /// Apply the calibration of a channel to a code, clamping the result to `0..=max_code`.
+    /// `None` for [`Channel::All`].
+    pub fn correct(&self, channel: Channel, code: u16, max_code: u16) -> Option<u16> {
+        Some(self.channel(channel)?.correct(code, max_code))
+    }
+
+    /// Apply the calibration of the channel at the index to a code
+    pub(crate) fn correct_index(&self, index: usize, code: u16, max_code: u16) -> u16 {
+        self.channels[index].correct(code, max_code)
+    }
+
+    /// Serialize the calibration into the buffer, returning the number of bytes written.
+    ///
+    /// The format starts with the version byte, followed by each channel's gain (i32),
+    /// offset (i16), number of table points (u8) and table points (two u16 each),
+    /// and ends with a CRC-16/CCITT-FALSE over all preceding bytes. All values are little-endian.
+    pub fn serialize(&self, buffer: &mut [u8]) -> Result<usize, CalibrationError> {
+        let len = 1
+            + self
+                .channels
+                .iter()
+                .map(|channel| CHANNEL_HEADER_LEN + channel.len as usize * 4)
+                .sum::<usize>()
+            + 2;
+        let buffer = buffer
+            .get_mut(..len)
+            .ok_or(CalibrationError::BufferTooSmall)?;
+
+        buffer[0] = FORMAT_VERSION;
+        let mut position = 1;
+        for channel in &self.channels {
+            buffer[position..position + 4].copy_from_slice(&channel.gain.to_le_bytes());
+            buffer[position + 4..position + 6].copy_from_slice(&channel.offset.to_le_bytes());
+            buffer[position + 6] = channel.len;
+            position += CHANNEL_HEADER_LEN;
+            for (input, output) in channel.table() {
+                buffer[position..position + 2].copy_from_slice(&input.to_le_bytes());
+                buffer[position + 2..position + 4].copy_from_slice(&output.to_le_bytes());
+                position += 4;
+            }
+        }
+        let crc = crc16(&buffer[..position]);
+        buffer[position..].copy_from_slice(&crc.to_le_bytes());
+        Ok(len)
+    }
+
+    /// Deserialize a calibration written by [`Calibration::serialize`]
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, CalibrationError> {
+        let version = *bytes.first().ok_or(CalibrationError::Truncated)?;
+        if version != FORMAT_VERSION {
+            return Err(CalibrationError::UnsupportedVersion(version));
+        }
+
+        let mut calibration = Calibration::new();
+        let mut position = 1;
+        for channel in calibration.channels.iter_mut() {
+            let header = bytes
+                .get(position..position + CHANNEL_HEADER_LEN)
+                .ok_or(CalibrationError::Truncated)?;
+            let gain = i32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let offset = i16::from_le_bytes([header[4], header[5]]);
+            let len = header[6] as usize;
+            position += CHANNEL_HEADER_LEN;
+            if len > MAX_POINTS {
+                return Err(CalibrationError::InvalidTable);
+            }
+
+            let mut points = [(0, 0); MAX_POINTS];
+            let table = bytes
+                .get(position..position + len * 4)
+                .ok_or(CalibrationError::Truncated)?;
+            for (point, chunk) in points.iter_mut().zip(table.chunks_exact(4)) {
+                *point = (
+                    u16::from_le_bytes([chunk[0], chunk[1]]),
+                    u16::from_le_bytes([chunk[2], chunk[3]]),
+                );
+            }
+            position += len * 4;
+
+            *channel = ChannelCalibration::new(gain, offset);
+            channel.set_table(&points[..len])?;
+        }
+
+        let crc = bytes
+            .get(position..position + 2)
+            .ok_or(CalibrationError::Truncated)?;
+        if crc16(&bytes[..position]) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Err(CalibrationError::CrcMismatch);
+        }
+        Ok(calibration)
+    }
+}
+
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xffff)
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
@@ -0,0 +1,126 @@
+use core::iter::FromIterator;
+use core::ops::{BitOr, BitOrAssign};
+
+use crate::Channel;
+
+/// Set of DAC output channels, stored as a bit mask with channel A in the least significant bit.
+///
+/// ```
+/// # use dac5578::*;
+/// let set = Channel::A | Channel::C;
+/// assert!(set.contains(Channel::C));
+/// assert_eq!(set.union(ChannelSet::from(Channel::H)).bits(), 0b1000_0101);
+/// assert_eq!(set.iter().collect::<Vec<_>>(), [Channel::A, Channel::C]);
+/// assert_eq!(ChannelSet::from(Channel::All), ChannelSet::ALL);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChannelSet(u8);
+
+impl ChannelSet {
+    /// Set without any channels
+    pub const EMPTY: ChannelSet = ChannelSet(0);
+
+    /// Set of all eight channels
+    pub const ALL: ChannelSet = ChannelSet(0xff);
+
+    /// Create a set from a bit mask with channel A in the least significant bit
+    pub const fn from_bits(bits: u8) -> Self {
+        ChannelSet(bits)
+    }
+
+    /// Bit mask with channel A in the least significant bit
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether the set contains the channel. [`Channel::All`] is contained by the full set only.
+    pub fn contains(self, channel: Channel) -> bool {
+        let bits = ChannelSet::from(channel).0;
+        self.0 & bits == bits
+    }
+
+    /// Add a channel to the set. [`Channel::All`] adds all channels.
+    pub fn insert(&mut self, channel: Channel) {
+        *self = self.union(channel.into());
+    }
+
+    /// Remove a channel from the set. [`Channel::All`] removes all channels.
+    pub fn remove(&mut self, channel: Channel) {
+        *self = self.difference(channel.into());
+    }
+
+    /// Channels in either set
+    pub const fn union(self, other: ChannelSet) -> Self {
+        ChannelSet(self.0 | other.0)
+    }
+
+    /// Channels in both sets
+    pub const fn intersection(self, other: ChannelSet) -> Self {
+        ChannelSet(self.0 & other.0)
+    }
+
+    /// Channels in this set but not in the other one
+    pub const fn difference(self, other: ChannelSet) -> Self {
+        ChannelSet(self.0 & !other.0)
+    }
+
+    /// Whether the set contains no channels
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Number of channels in the set
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Iterate over the channels in the set, from A to H
+    pub fn iter(self) -> impl Iterator<Item = Channel> {
+        Channel::iter().filter(move |&channel| self.contains(channel))
+    }
+}
+
+impl From<Channel> for ChannelSet {
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::All => ChannelSet::ALL,
+            channel => ChannelSet(1 << channel as u8),
+        }
+    }
+}
+
+impl<const N: usize> From<[Channel; N]> for ChannelSet {
+    fn from(channels: [Channel; N]) -> Self {
+        channels.iter().copied().collect()
+    }
+}
+
+impl FromIterator<Channel> for ChannelSet {
+    fn from_iter<I: IntoIterator<Item = Channel>>(channels: I) -> Self {
+        channels
+            .into_iter()
+            .fold(ChannelSet::EMPTY, |set, channel| set | channel)
+    }
+}
+
+impl<T: Into<ChannelSet>> BitOr<T> for ChannelSet {
+    type Output = ChannelSet;
+
+    fn bitor(self, other: T) -> ChannelSet {
+        self.union(other.into())
+    }
+}
+
+impl<T: Into<ChannelSet>> BitOrAssign<T> for ChannelSet {
+    fn bitor_assign(&mut self, other: T) {
+        *self = self.union(other.into());
+    }
+}
+
+impl<T: Into<ChannelSet>> BitOr<T> for Channel {
+    type Output = ChannelSet;
+
+    fn bitor(self, other: T) -> ChannelSet {
+        ChannelSet::from(self).union(other.into())
+    }
+}
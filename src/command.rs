@@ -0,0 +1,119 @@
+//! Encoding of the commands shared by the blocking and the async driver
+
+use crate::calibration::Calibration;
+use crate::{Channel, ChannelSet, ClearCode, CommandType, ResetMode, Resolution};
+
+/// Address of the I2C general call
+pub(crate) const GENERAL_CALL_ADDRESS: u8 = 0x00;
+
+/// General call wake-up command
+pub(crate) const WAKE_UP: [u8; 1] = [0x06];
+
+/// General call reset command
+pub(crate) const RESET: [u8; 1] = [0x09];
+
+/// Power down bits that power a channel up
+pub(crate) const POWER_UP: u8 = 0b00;
+
+/// Encode command type, channel and code into a three byte command.
+/// The code is clamped to the resolution of the part and left-justified.
+pub(crate) fn encode<R: Resolution>(command: CommandType, access: u8, code: u16) -> [u8; 3] {
+    let value = code.min(R::MAX_CODE) << (16 - R::BITS);
+    let value_bytes = value.to_be_bytes();
+    [command as u8 | access, value_bytes[0], value_bytes[1]]
+}
+
+/// Short sequence of commands, e.g. one per channel
+pub(crate) struct Commands {
+    bytes: [[u8; 3]; 16],
+    len: usize,
+}
+
+impl Commands {
+    pub(crate) fn new() -> Self {
+        Commands {
+            bytes: [[0; 3]; 16],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, bytes: [u8; 3]) {
+        self.bytes[self.len] = bytes;
+        self.len += 1;
+    }
+
+    pub(crate) fn as_slice(&self) -> &[[u8; 3]] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Encode a write of a code, applying the channel's calibration.
+/// As every channel has its own calibration, a calibrated write to [`Channel::All`] is
+/// expanded into one command per channel. A global update is only sent with the last one.
+pub(crate) fn encode_calibrated<R: Resolution>(
+    command: CommandType,
+    channel: Channel,
+    code: u16,
+    calibration: Option<&Calibration>,
+) -> Commands {
+    let mut commands = Commands::new();
+    match (calibration, channel) {
+        (None, channel) => commands.push(encode::<R>(command, channel as u8, code)),
+        (Some(calibration), Channel::All) => {
+            for index in 0..8 {
+                let command = match command {
+                    CommandType::WriteToChannelAndUpdateAll if index < 7 => {
+                        CommandType::WriteToChannel
+                    }
+                    command => command,
+                };
+                let code = calibration.correct_index(index as usize, code, R::MAX_CODE);
+                commands.push(encode::<R>(command, index, code));
+            }
+        }
+        (Some(calibration), channel) => {
+            let access = channel as u8;
+            let code = calibration.correct_index(access as usize, code, R::MAX_CODE);
+            commands.push(encode::<R>(command, access, code));
+        }
+    }
+    commands
+}
+
+/// Decode a left-justified register value into a code of the part's resolution
+pub(crate) fn decode<R: Resolution>(bytes: [u8; 2]) -> u16 {
+    u16::from_be_bytes(bytes) >> (16 - R::BITS)
+}
+
+/// Encode a software reset using the selected mode
+pub(crate) fn reset(mode: ResetMode) -> [u8; 3] {
+    [0x70, mode as u8, 0]
+}
+
+/// Encode the power down bits for the given channels.
+/// The power down bits occupy DB14..DB13, followed by one select bit per channel (H..A) in DB12..DB5.
+pub(crate) fn power(channels: ChannelSet, bits: u8) -> [u8; 3] {
+    let value = (bits as u16) << 13 | (channels.bits() as u16) << 5;
+    let value_bytes = value.to_be_bytes();
+    [CommandType::PowerDown as u8, value_bytes[0], value_bytes[1]]
+}
+
+/// Encode the clear code. The clear code bits occupy DB5..DB4.
+pub(crate) fn clear_code(code: ClearCode) -> [u8; 3] {
+    [CommandType::ClearCode as u8, 0, (code as u8) << 4]
+}
+
+/// Decode the clear code from the read back register
+pub(crate) fn decode_clear_code(bytes: [u8; 2]) -> ClearCode {
+    ClearCode::from_bits(bytes[1] >> 4)
+}
+
+/// Encode the LDAC mask. The LDAC bits for channels H..A occupy DB15..DB8.
+pub(crate) fn ldac(channels: ChannelSet) -> [u8; 3] {
+    [CommandType::Ldac as u8, channels.bits(), 0]
+}
+
+/// Decode the LDAC mask from the read back register
+pub(crate) fn decode_ldac(bytes: [u8; 2]) -> ChannelSet {
+    ChannelSet::from_bits(bytes[0])
+}
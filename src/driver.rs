@@ -0,0 +1,242 @@
+//! State and logic shared by the blocking and the async driver.
+//!
+//! [`Core`] checks the arguments, encodes the commands and keeps the cache, the redundant
+//! write counter and the High-Speed mode state up to date. The drivers only perform the bus
+//! transfers in between, so the two can't drift apart.
+
+use core::marker::PhantomData;
+
+use crate::cache::Cache;
+use crate::calibration::Calibration;
+use crate::command::{self, Commands};
+use crate::{
+    Address, Channel, ChannelSet, ClearCode, CommandType, DesiredState, Error, Register, Resolution,
+};
+
+/// Configuration and tracked device state of a driver
+#[derive(Debug)]
+pub(crate) struct Core<R> {
+    pub(crate) address: u8,
+    pub(crate) vref: Option<u32>,
+    pub(crate) calibration: Option<Calibration>,
+    pub(crate) cache: Option<Cache>,
+    pub(crate) skip_redundant: bool,
+    pub(crate) skipped_writes: u32,
+    pub(crate) high_speed: bool,
+    resolution: PhantomData<R>,
+}
+
+impl<R: Resolution> Core<R> {
+    pub(crate) fn new(address: Address) -> Self {
+        Core {
+            address: address as u8,
+            vref: None,
+            calibration: None,
+            cache: None,
+            skip_redundant: false,
+            skipped_writes: 0,
+            high_speed: false,
+            resolution: PhantomData,
+        }
+    }
+
+    /// Leave out redundant commands, enabling the cache if necessary
+    pub(crate) fn set_skip_redundant(&mut self, skip: bool) {
+        if skip && self.cache.is_none() {
+            self.cache = Some(Cache::new());
+        }
+        self.skip_redundant = skip;
+    }
+
+    /// Commands writing a code to the channel's input register, applying the calibration.
+    /// Fails with [`Error::CodeOutOfRange`] for codes above the maximum of the part.
+    pub(crate) fn write<E>(
+        &self,
+        command: CommandType,
+        channel: Channel,
+        code: u16,
+    ) -> Result<Commands, Error<E>> {
+        check::<R, E>(code)?;
+        Ok(command::encode_calibrated::<R>(
+            command,
+            channel,
+            code,
+            self.calibration.as_ref(),
+        ))
+    }
+
+    /// Command writing a code the calibration was already applied to.
+    /// Fails with [`Error::CodeOutOfRange`] for codes above the maximum of the part.
+    pub(crate) fn write_uncalibrated<E>(
+        &self,
+        command: CommandType,
+        channel: Channel,
+        code: u16,
+    ) -> Result<[u8; 3], Error<E>> {
+        check::<R, E>(code)?;
+        Ok(command::encode::<R>(command, channel as u8, code))
+    }
+
+    /// Commands writing each channel of the set to its input register, the last one with the
+    /// given command. All codes of the set are checked before any command is encoded.
+    pub(crate) fn write_channels<E>(
+        &self,
+        channels: ChannelSet,
+        codes: &[u16; 8],
+        last: CommandType,
+    ) -> Result<Commands, Error<E>> {
+        for channel in channels.iter() {
+            check::<R, E>(codes[channel as usize])?;
+        }
+        let mut commands = Commands::new();
+        for (index, channel) in channels.iter().enumerate() {
+            let command = if index + 1 == channels.len() {
+                last
+            } else {
+                CommandType::WriteToChannel
+            };
+            let code = codes[channel as usize];
+            for bytes in self.write(command, channel, code)?.as_slice() {
+                commands.push(*bytes);
+            }
+        }
+        Ok(commands)
+    }
+
+    /// Code the channel's DAC register holds after writing the code, for verified writes.
+    /// Fails with [`Error::InvalidChannel`] for [`Channel::All`].
+    pub(crate) fn expected<E>(&self, channel: Channel, code: u16) -> Result<u16, Error<E>> {
+        let index = channel.index().ok_or(Error::InvalidChannel)?;
+        Ok(match &self.calibration {
+            Some(calibration) => calibration.correct_index(index, code, R::MAX_CODE),
+            None => code,
+        })
+    }
+
+    /// Code for the output voltage in microvolts and the voltage it actually sets.
+    /// Fails with [`Error::NoReference`] if no reference voltage is configured.
+    pub(crate) fn microvolts<E>(&self, microvolts: u32) -> Result<(u16, u32), Error<E>> {
+        let vref = self.vref.ok_or(Error::NoReference)?;
+        let code = R::code_for_microvolts(microvolts, vref);
+        Ok((code, R::microvolts_for_code(code, vref)))
+    }
+
+    /// Channels to read back before planning the desired state, enabling the cache if
+    /// necessary. Fails with [`Error::CodeOutOfRange`] if a target code is above the maximum.
+    pub(crate) fn unknown<E>(&mut self, state: &DesiredState) -> Result<ChannelSet, Error<E>> {
+        if let Some(code) = state.out_of_range::<R>() {
+            return Err(Error::CodeOutOfRange(code));
+        }
+        let cache = self.cache.get_or_insert_with(Cache::new);
+        Ok(state.unknown::<R>(cache, self.calibration.as_ref()))
+    }
+
+    /// Commands moving the device from the cached state to the desired state
+    pub(crate) fn plan(&mut self, state: &DesiredState) -> Commands {
+        let cache = self.cache.get_or_insert_with(Cache::new);
+        state.plan::<R>(cache, self.calibration.as_ref())
+    }
+
+    /// Whether to leave out the command, counting it if so.
+    /// Unless forced, redundant commands are left out if enabled.
+    pub(crate) fn skip(&mut self, bytes: &[u8; 3], force: bool) -> bool {
+        match (&self.cache, self.skip_redundant, force) {
+            (Some(cache), true, false) if cache.is_redundant::<R>(bytes) => {
+                self.skipped_writes = self.skipped_writes.wrapping_add(1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record a three byte command sent to the device, including the High-Speed mode a
+    /// software reset leaves it in
+    pub(crate) fn sent(&mut self, bytes: &[u8; 3]) {
+        if let [0x70, mode, _] = *bytes {
+            match mode & 0b11 {
+                0b00 => self.high_speed = false,
+                0b01 => self.high_speed = true,
+                _ => {}
+            }
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.record::<R>(bytes);
+        }
+    }
+
+    /// Record a general call sent on the bus. A reset leaves High-Speed mode.
+    pub(crate) fn general_call_sent(&mut self, bytes: &[u8; 1]) {
+        if *bytes == command::RESET {
+            self.high_speed = false;
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.record_general_call(bytes);
+        }
+    }
+
+    /// Command byte reading back a register for a channel.
+    /// Fails with [`Error::InvalidChannel`] for [`Channel::All`].
+    pub(crate) fn read<E>(&self, register: Register, channel: Channel) -> Result<u8, Error<E>> {
+        if let Channel::All = channel {
+            return Err(Error::InvalidChannel);
+        }
+        Ok(register as u8 | channel as u8)
+    }
+
+    /// Decode and record a register read back for a channel
+    pub(crate) fn register_read(
+        &mut self,
+        register: Register,
+        channel: Channel,
+        bytes: [u8; 2],
+    ) -> u16 {
+        let code = command::decode::<R>(bytes);
+        if let Some(cache) = &mut self.cache {
+            match register {
+                Register::Input => cache.record_input(channel, code),
+                Register::Dac => cache.record_dac(channel, code),
+            }
+        }
+        code
+    }
+
+    /// Decode and record the clear code read back
+    pub(crate) fn clear_code_read(&mut self, bytes: [u8; 2]) -> ClearCode {
+        let code = command::decode_clear_code(bytes);
+        if let Some(cache) = &mut self.cache {
+            cache.record_clear_code(code);
+        }
+        code
+    }
+
+    /// Decode and record the LDAC mask read back
+    pub(crate) fn ldac_mask_read(&mut self, bytes: [u8; 2]) -> ChannelSet {
+        let channels = command::decode_ldac(bytes);
+        if let Some(cache) = &mut self.cache {
+            cache.record_ldac_mask(channels);
+        }
+        channels
+    }
+
+    /// Record a pulse of the LDAC pin
+    pub(crate) fn ldac_pulsed(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.record_ldac_pulse();
+        }
+    }
+
+    /// Record the CLR pin being asserted
+    pub(crate) fn cleared(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.record_clear::<R>();
+        }
+    }
+}
+
+/// Fail with [`Error::CodeOutOfRange`] for codes above the maximum of the part
+fn check<R: Resolution, E>(code: u16) -> Result<(), Error<E>> {
+    if code > R::MAX_CODE {
+        return Err(Error::CodeOutOfRange(code));
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! Support for I2C peripherals implementing the embedded-hal 0.2 blocking traits.
+//!
+//! Wrap the peripheral in [`Compat`] to use it with the driver:
+//! ```
+//! # use embedded_hal_mock::eh0::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # use dac5578::eh02::Compat;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write_read(0x48, vec![0x10], vec![0x80, 0x00]),
+//! # ]);
+//! let mut dac = DAC5578::new(Compat::new(i2c), Address::PinLow);
+//! dac.write_and_update(Channel::A, 128).unwrap();
+//! assert_eq!(dac.read_dac(Channel::A).unwrap(), 128);
+//! # dac.destroy().into_inner().done();
+//! ```
+//!
+//! All commands work through the adapter, including entering High-Speed mode:
+//! ```
+//! # use embedded_hal_mock::eh0::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # use dac5578::eh02::Compat;
+//! # let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x70, 0x01, 0x00])]);
+//! let mut dac = DAC5578::new(Compat::new(i2c), Address::PinLow);
+//! dac.enter_high_speed().unwrap();
+//! assert!(dac.is_high_speed());
+//! # dac.destroy().into_inner().done();
+//! ```
+//!
+//! The 0.2 traits send at most a write followed by a read in one transfer. Transactions of the
+//! 1.0 trait are sent as one transfer if they fit: adjacent writes are joined, and a single
+//! read at the end follows with a repeated start. Other transactions fail with
+//! [`CompatError::UnsupportedTransaction`] without sending anything:
+//! ```
+//! # use embedded_hal_mock::eh0::i2c::{Mock, Transaction};
+//! # use dac5578::eh02::{Compat, CompatError};
+//! use embedded_hal::i2c::{I2c, Operation};
+//! # let mut i2c = Mock::new(&[Transaction::write_read(0x48, vec![0x10, 0x11], vec![0x80, 0x00])]);
+//! let mut i2c = Compat::new(i2c);
+//! let mut buffer = [0; 2];
+//! i2c.transaction(
+//!     0x48,
+//!     &mut [Operation::Write(&[0x10]), Operation::Write(&[0x11]), Operation::Read(&mut buffer)],
+//! )
+//! .unwrap();
+//! assert_eq!(buffer, [0x80, 0x00]);
+//!
+//! let (mut first, mut second) = ([0; 2], [0; 2]);
+//! let result = i2c.transaction(0x48, &mut [Operation::Read(&mut first), Operation::Read(&mut second)]);
+//! assert!(matches!(result, Err(CompatError::UnsupportedTransaction)));
+//! # i2c.into_inner().done();
+//! ```
+
+use core::fmt::Debug;
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, Operation, SevenBitAddress};
+use embedded_hal_02::blocking::i2c::{Read, Write, WriteRead};
+
+/// Adapter implementing the embedded-hal 1.0 [`I2c`] trait for an embedded-hal 0.2 I2C peripheral
+#[derive(Debug)]
+pub struct Compat<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C> Compat<I2C> {
+    /// Wrap an embedded-hal 0.2 I2C peripheral
+    pub fn new(i2c: I2C) -> Self {
+        Compat { i2c }
+    }
+
+    /// Return the wrapped I2C peripheral
+    pub fn into_inner(self) -> I2C {
+        self.i2c
+    }
+}
+
+/// Maximum number of bytes of adjacent writes joined into one transfer
+const JOINED_WRITES: usize = 32;
+
+/// Error of an embedded-hal 0.2 I2C peripheral.
+/// The 0.2 traits don't classify errors, so the kind is always [`ErrorKind::Other`].
+#[derive(Debug)]
+pub enum CompatError<E> {
+    /// Error of the wrapped peripheral
+    I2c(E),
+    /// The operations of the transaction can't be sent as one transfer with the 0.2 traits
+    UnsupportedTransaction,
+}
+
+impl<E: Debug> embedded_hal::i2c::Error for CompatError<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<I2C, E> ErrorType for Compat<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    type Error = CompatError<E>;
+}
+
+impl<I2C, E> I2c<SevenBitAddress> for Compat<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.i2c.read(address, read).map_err(CompatError::I2c)
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.i2c.write(address, write).map_err(CompatError::I2c)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.i2c
+            .write_read(address, write, read)
+            .map_err(CompatError::I2c)
+    }
+
+    /// The 0.2 traits can't chain arbitrary operations, so adjacent writes are joined and a
+    /// single read at the end follows with a repeated start. Other transactions fail with
+    /// [`CompatError::UnsupportedTransaction`] before anything is sent.
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let split = operations
+            .iter()
+            .position(|operation| matches!(operation, Operation::Read(_)))
+            .unwrap_or(operations.len());
+        let (writes, reads) = operations.split_at_mut(split);
+        let mut buffer = [0u8; JOINED_WRITES];
+        let write: &[u8] = match writes {
+            [Operation::Write(write)] => write,
+            _ => {
+                let mut len = 0;
+                for operation in writes.iter() {
+                    if let Operation::Write(write) = operation {
+                        buffer
+                            .get_mut(len..len + write.len())
+                            .ok_or(CompatError::UnsupportedTransaction)?
+                            .copy_from_slice(write);
+                        len += write.len();
+                    }
+                }
+                &buffer[..len]
+            }
+        };
+        match reads {
+            [] if writes.is_empty() => Ok(()),
+            [] => self.write(address, write),
+            [Operation::Read(read)] if writes.is_empty() => self.read(address, read),
+            [Operation::Read(read)] => self.write_read(address, write, read),
+            _ => Err(CompatError::UnsupportedTransaction),
+        }
+    }
+}
@@ -1,28 +1,238 @@
-//! *Texas Instruments DAC5578 Driver for Rust Embedded HAL*
+//! *Texas Instruments DACx578 Driver for Rust Embedded HAL*
 //! This is a driver crate for embedded Rust. It's built on top of the Rust
 //! [embedded HAL](https://github.com/rust-embedded/embedded-hal)
-//! It supports sending commands to a TI DAC5578 over I2C.
+//! It supports sending commands to a TI DAC5578/DAC6578/DAC7578 over I2C.
 //!
 //! The driver can be initialized by calling create and passing it an I2C interface.
 //! The device address (set by ADDR0) also needs to be specified.
 //! It can be set by pulling the ADDR0 on the device high/low or floating.
 //!
 //! ```
-//! # use embedded_hal_mock::i2c::Mock;
+//! # use embedded_hal_mock::eh1::i2c::Mock;
 //! # use dac5578::*;
 //! # let mut i2c = Mock::new(&[]);
 //! let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! # dac.destroy().done();
 //! ```
 //!
 //! To set the dac output for channel A:
 //! ```
-//! # use embedded_hal_mock::i2c::{Mock, Transaction};
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
 //! # use dac5578::*;
-//! # let mut i2c = Mock::new(&[Transaction::write(98, vec![0x40, 0xff, 0xf0]),]);
+//! # let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x30, 0x80, 0x00]),]);
 //! # let mut dac = DAC5578::new(i2c, Address::PinLow);
-//! dac.write_channel(Channel::A, 128);
+//! dac.write_and_update(Channel::A, 128);
+//! # dac.destroy().done();
 //! ```
 //!
+//! To read back what the device holds for channel A:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write_read(0x48, vec![0x00], vec![0x80, 0x00]),
+//! #     Transaction::write_read(0x48, vec![0x10], vec![0x40, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! let input = dac.read_input(Channel::A).unwrap();
+//! let output = dac.read_dac(Channel::A).unwrap();
+//! # assert_eq!(input, 128);
+//! # assert_eq!(output, 64);
+//! # dac.destroy().done();
+//! ```
+//!
+//! Channels can be parsed from their names and iterated:
+//! ```
+//! # use dac5578::*;
+//! # use core::convert::TryFrom;
+//! assert_eq!("c".parse::<Channel>(), Ok(Channel::C));
+//! assert_eq!(Channel::try_from('H'), Ok(Channel::H));
+//! assert_eq!(Channel::try_from(8), Err(InvalidChannel));
+//! assert_eq!(Channel::iter().count(), 8);
+//! ```
+//!
+//! Several channels can be written at once, latching all outputs together with the last write:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x00, 0x0a, 0x00]),
+//! #     Transaction::write(0x48, vec![0x23, 0x28, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! let codes = [10, 20, 30, 40, 50, 60, 70, 80];
+//! dac.write_and_update_channels(Channel::A | Channel::D, &codes).unwrap();
+//! # dac.destroy().done();
+//! ```
+//!
+//! Besides bus errors, the driver reports its own failures through [`Error`],
+//! e.g. when a write can't be confirmed by reading it back:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write_read(0x48, vec![0x10], vec![0x00, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! assert_eq!(
+//!     dac.write_and_update_verified(Channel::A, 128),
+//!     Err(Error::ReadbackMismatch { expected: 128, actual: 0 })
+//! );
+//! assert_eq!(dac.read_dac(Channel::All), Err(Error::InvalidChannel));
+//! assert_eq!(dac.write_and_update_microvolts(Channel::A, 1_000), Err(Error::NoReference));
+//! # dac.destroy().done();
+//! ```
+//!
+//! Unused channels can be powered down:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x40, 0x7c, 0x00]),
+//! #     Transaction::write(0x48, vec![0x40, 0x00, 0x20]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.power_down([Channel::F, Channel::G, Channel::H], PowerDownMode::HighImpedance).unwrap();
+//! dac.power_up([Channel::A]).unwrap();
+//! # dac.destroy().done();
+//! ```
+//!
+//! The outputs can be configured to go to mid-scale when the CLR pin is asserted:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x50, 0x00, 0x10]),
+//! #     Transaction::write_read(0x48, vec![0x50], vec![0x00, 0x10]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.set_clear_code(ClearCode::MidScale).unwrap();
+//! assert_eq!(dac.read_clear_code().unwrap(), ClearCode::MidScale);
+//! # dac.destroy().done();
+//! ```
+//!
+//! Channels can be excluded from the hardware LDAC pin, leaving the others to latch on it:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x60, 0xc0, 0x00]),
+//! #     Transaction::write_read(0x48, vec![0x60], vec![0xc0, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.set_ldac_mask([Channel::G, Channel::H]).unwrap();
+//! assert_eq!(dac.read_ldac_mask().unwrap(), Channel::G | Channel::H);
+//! # dac.destroy().done();
+//! ```
+//!
+//! With the reference voltage configured, outputs can be set in volts or microvolts.
+//! The voltage actually set after quantization is returned:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x66, 0x00]),
+//! #     Transaction::write(0x48, vec![0x31, 0xff, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.set_reference(2_500_000);
+//! assert_eq!(dac.write_and_update_microvolts(Channel::A, 1_000_000).unwrap(), 996_093);
+//! assert_eq!(dac.write_and_update_volts(Channel::B, 3.3).unwrap(), 2.490234);
+//! # dac.destroy().done();
+//! ```
+//!
+//! The DAC6578 and DAC7578 take 10 and 12 bit codes respectively.
+//! The driver left-justifies them and rejects codes above the part's maximum:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! # ]);
+//! let mut dac = DAC7578::new(i2c, Address::PinLow);
+//! dac.write_and_update(Channel::A, 2048).unwrap();
+//! assert_eq!(dac.write_and_update(Channel::B, 5000), Err(Error::CodeOutOfRange(5000)));
+//! # dac.destroy().done();
+//! ```
+//!
+//! For faster update rates the device can be driven in High-Speed (3.4 MHz) mode. The driver
+//! puts the device into High-Speed mode with a software reset and tracks the mode across resets:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x70, 0x01, 0x00]),
+//! #     Transaction::write(0x48, vec![0x70, 0x02, 0x00]),
+//! #     Transaction::write(0x48, vec![0x70, 0x00, 0x00]),
+//! # ]);
+//! let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! dac.enter_high_speed().unwrap();
+//! assert!(dac.is_high_speed());
+//! // Switch the I2C controller to 3.4 MHz here
+//! dac.reset(ResetMode::MaintainHighSpeed).unwrap();
+//! assert!(dac.is_high_speed());
+//! // Switch the I2C controller back to F/S speed here
+//! dac.leave_high_speed().unwrap();
+//! assert!(!dac.is_high_speed());
+//! # dac.destroy().done();
+//! ```
+//!
+//! The driver takes any [`I2c`] implementation, so a bus shared with other devices is handed
+//! over as one of the `embedded-hal-bus` devices. [`RefCellDevice`] shares it within a single
+//! execution context:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use core::cell::RefCell;
+//! use embedded_hal_bus::i2c::RefCellDevice;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write(0x4a, vec![0x31, 0x40, 0x00]),
+//! # ]);
+//! let bus = RefCell::new(i2c);
+//! let mut dac = DAC5578::new(RefCellDevice::new(&bus), Address::PinLow);
+//! let mut other = DAC5578::new(RefCellDevice::new(&bus), Address::PinHigh);
+//! dac.write_and_update(Channel::A, 128).unwrap();
+//! other.write_and_update(Channel::B, 64).unwrap();
+//! # drop((dac, other));
+//! # bus.into_inner().done();
+//! ```
+//!
+//! [`CriticalSectionDevice`] shares it with interrupt handlers, and [`MutexDevice`] with other
+//! threads on platforms with `std`:
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use core::cell::RefCell;
+//! use critical_section::Mutex;
+//! use embedded_hal_bus::i2c::{CriticalSectionDevice, MutexDevice};
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! # ]);
+//! let bus = Mutex::new(RefCell::new(i2c));
+//! let mut dac = DAC5578::new(CriticalSectionDevice::new(&bus), Address::PinLow);
+//! dac.write_and_update(Channel::A, 128).unwrap();
+//! # drop(dac);
+//! # bus.into_inner().into_inner().done();
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x4c, vec![0x30, 0x80, 0x00]),
+//! # ]);
+//!
+//! let bus = std::sync::Mutex::new(i2c);
+//! std::thread::scope(|scope| {
+//!     scope.spawn(|| {
+//!         let mut dac = DAC5578::new(MutexDevice::new(&bus), Address::PinFloat);
+//!         dac.write_and_update(Channel::A, 128).unwrap();
+//!     });
+//! });
+//! # bus.into_inner().unwrap().done();
+//! ```
+//!
+//! [`RefCellDevice`]: https://docs.rs/embedded-hal-bus/latest/embedded_hal_bus/i2c/struct.RefCellDevice.html
+//! [`CriticalSectionDevice`]: https://docs.rs/embedded-hal-bus/latest/embedded_hal_bus/i2c/struct.CriticalSectionDevice.html
+//! [`MutexDevice`]: https://docs.rs/embedded-hal-bus/latest/embedded_hal_bus/i2c/struct.MutexDevice.html
+//!
 //! ## More information
 //! - [DAC5578 datasheet](https://www.ti.com/lit/ds/symlink/dac5578.pdf?ts=1621340690413&ref_url=https%253A%252F%252Fwww.ti.com%252Fproduct%252FDAC5578)
 //! - [API documentation](https://docs.rs/dac5578/)
@@ -32,11 +242,44 @@
 #![no_std]
 #![warn(missing_debug_implementations, missing_docs)]
 
+#[cfg(feature = "sim")]
+extern crate std;
+
+use cache::Cache;
+use calibration::Calibration;
+use command::Commands;
+use core::convert::TryFrom;
 use core::fmt::Debug;
-use embedded_hal::blocking::i2c::{Read, Write};
+use core::str::FromStr;
+use driver::Core;
+use embedded_hal::i2c::I2c;
+
+pub use channel_set::ChannelSet;
+pub use pins::NoPin;
+pub use state::DesiredState;
+
+pub mod cache;
+pub mod calibration;
+mod channel_set;
+mod command;
+mod driver;
+pub mod multi;
+mod pins;
+pub mod playback;
+pub mod ramp;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod split;
+mod state;
+pub mod waveform;
+
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(feature = "eh02")]
+pub mod eh02;
 
 /// user_address can be set by pulling the ADDR0 pin high/low or leave it floating
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Address {
     /// ADDR0 is low
@@ -48,7 +291,7 @@ pub enum Address {
 }
 
 /// Defines the output channel to set the voltage for
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Channel {
     /// DAC output channel A
@@ -71,24 +314,130 @@ pub enum Channel {
     All = 0xf,
 }
 
-impl From<u8> for Channel {
-    fn from(index: u8) -> Self {
+impl TryFrom<u8> for Channel {
+    type Error = InvalidChannel;
+
+    fn try_from(index: u8) -> Result<Self, Self::Error> {
         match index {
-            0 => Channel::A,
-            1 => Channel::B,
-            2 => Channel::C,
-            3 => Channel::D,
-            4 => Channel::E,
-            5 => Channel::F,
-            6 => Channel::G,
-            7 => Channel::H,
-            _ => panic!("Unkown channel number {}", index),
+            0 => Ok(Channel::A),
+            1 => Ok(Channel::B),
+            2 => Ok(Channel::C),
+            3 => Ok(Channel::D),
+            4 => Ok(Channel::E),
+            5 => Ok(Channel::F),
+            6 => Ok(Channel::G),
+            7 => Ok(Channel::H),
+            _ => Err(InvalidChannel),
+        }
+    }
+}
+
+impl TryFrom<char> for Channel {
+    type Error = InvalidChannel;
+
+    /// Convert a channel letter `'A'..='H'`, ignoring case
+    fn try_from(letter: char) -> Result<Self, Self::Error> {
+        match letter.to_ascii_uppercase() {
+            letter @ 'A'..='H' => Channel::try_from(letter as u8 - b'A'),
+            _ => Err(InvalidChannel),
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = InvalidChannel;
+
+    /// Parse a channel name `"A"..="H"`, ignoring case
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let mut letters = name.chars();
+        match (letters.next(), letters.next()) {
+            (Some(letter), None) => Channel::try_from(letter),
+            _ => Err(InvalidChannel),
+        }
+    }
+}
+
+impl Channel {
+    /// Iterate over the eight physical channels A to H
+    pub fn iter() -> impl Iterator<Item = Channel> {
+        [
+            Channel::A,
+            Channel::B,
+            Channel::C,
+            Channel::D,
+            Channel::E,
+            Channel::F,
+            Channel::G,
+            Channel::H,
+        ]
+        .iter()
+        .copied()
+    }
+
+    /// Index of a single channel, `None` for [`Channel::All`]
+    pub(crate) fn index(self) -> Option<usize> {
+        match self {
+            Channel::All => None,
+            channel => Some(channel as usize),
+        }
+    }
+}
+
+/// Error converting a value that doesn't name a single channel into a [`Channel`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidChannel;
+
+/// Errors of the driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Error of the I2C bus
+    I2c(E),
+    /// The code exceeds the maximum of the part's resolution
+    CodeOutOfRange(u16),
+    /// The channel can't be used with the command, e.g. reading back [`Channel::All`]
+    InvalidChannel,
+    /// The value read back doesn't match the value written
+    ReadbackMismatch {
+        /// Value that was written
+        expected: u16,
+        /// Value that was read back
+        actual: u16,
+    },
+    /// No reference voltage is configured for the voltage based methods
+    NoReference,
+    /// Error of the LDAC or CLR output pin
+    Pin(embedded_hal::digital::ErrorKind),
+}
+
+impl<E> From<InvalidChannel> for Error<E> {
+    fn from(_: InvalidChannel) -> Self {
+        Error::InvalidChannel
+    }
+}
+
+impl<E: embedded_hal::i2c::Error> Error<E> {
+    /// Kind of the I2C bus error, `None` for the other errors
+    ///
+    /// ```
+    /// use dac5578::Error;
+    /// use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+    ///
+    /// let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+    /// assert_eq!(Error::I2c(nack).kind(), Some(nack));
+    /// assert_eq!(Error::<ErrorKind>::NoReference.kind(), None);
+    /// ```
+    pub fn kind(&self) -> Option<embedded_hal::i2c::ErrorKind> {
+        match self {
+            Error::I2c(error) => Some(error.kind()),
+            _ => None,
         }
     }
 }
 
 /// The type of the command to send for a Command
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum CommandType {
     /// Write to the channel's DAC input register
@@ -99,10 +448,72 @@ pub enum CommandType {
     WriteToChannelAndUpdate = 0x30,
     /// Write to Selected DAC Input Register and Update All DAC Registers (Global Software LDAC)
     WriteToChannelAndUpdateAll = 0x20,
+    /// Power down or power up DAC channels
+    PowerDown = 0x40,
+    /// Write to the clear code register
+    ClearCode = 0x50,
+    /// Write to the LDAC register
+    Ldac = 0x60,
+}
+
+/// Output state of powered down DAC channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PowerDownMode {
+    /// Output is connected to GND through a 1 kΩ resistor
+    Pulldown1K = 0b01,
+    /// Output is connected to GND through a 100 kΩ resistor
+    Pulldown100K = 0b10,
+    /// Output is left in high impedance
+    HighImpedance = 0b11,
+}
+
+/// Power state of a DAC channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    /// The channel is powered up
+    Up,
+    /// The channel is powered down with its output in the given mode
+    Down(PowerDownMode),
+}
+
+/// Code the DAC outputs are set to when the CLR pin is asserted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClearCode {
+    /// Clear to zero-scale (default)
+    ZeroScale = 0b00,
+    /// Clear to mid-scale
+    MidScale = 0b01,
+    /// Clear to full-scale
+    FullScale = 0b10,
+    /// Ignore the CLR pin
+    Ignore = 0b11,
+}
+
+impl ClearCode {
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => ClearCode::ZeroScale,
+            0b01 => ClearCode::MidScale,
+            0b10 => ClearCode::FullScale,
+            _ => ClearCode::Ignore,
+        }
+    }
+}
+
+/// Registers that can be read back from the DAC5578
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Register {
+    /// The channel's DAC input register
+    Input = 0x0,
+    /// The channel's DAC register, i.e. the value currently driving the output
+    Dac = 0x10,
 }
 
 /// Two bit flags indicating the reset mode for the DAC5578
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ResetMode {
     /// Software reset (default). Same as power-on reset (POR).
@@ -113,82 +524,492 @@ pub enum ResetMode {
     MaintainHighSpeed = 0b10,
 }
 
-/// DAC5578 driver. Wraps an I2C port to send commands to a DAC5578
+/// Resolution of a part of the DACx578 family, implemented by [`Bits8`], [`Bits10`] and
+/// [`Bits12`] only
+///
+/// ```compile_fail
+/// struct Bits16;
+///
+/// impl dac5578::Resolution for Bits16 {
+///     const BITS: u8 = 16;
+/// }
+/// ```
+pub trait Resolution: sealed::Sealed {
+    /// Number of bits of the DAC code
+    const BITS: u8;
+    /// Largest code accepted by the part
+    const MAX_CODE: u16 = ((1u32 << Self::BITS) - 1) as u16;
+
+    /// Convert an output voltage in microvolts into the nearest code for the given reference
+    /// voltage (`VOUT = code / 2^BITS * VREF`). Voltages above the full-scale output are clamped.
+    fn code_for_microvolts(microvolts: u32, vref_microvolts: u32) -> u16 {
+        if vref_microvolts == 0 {
+            return 0;
+        }
+        let vref = vref_microvolts as u64;
+        let code = ((microvolts as u64) << Self::BITS) + vref / 2;
+        (code / vref).min(Self::MAX_CODE as u64) as u16
+    }
+
+    /// Convert a code into the output voltage in microvolts for the given reference voltage
+    fn microvolts_for_code(code: u16, vref_microvolts: u32) -> u32 {
+        ((code.min(Self::MAX_CODE) as u64 * vref_microvolts as u64) >> Self::BITS) as u32
+    }
+}
+
+/// 8 bit resolution of the DAC5578
 #[derive(Debug)]
-pub struct DAC5578<I2C>
-where
-    I2C: Read + Write,
-{
+pub struct Bits8;
+
+impl sealed::Sealed for Bits8 {}
+
+impl Resolution for Bits8 {
+    const BITS: u8 = 8;
+}
+
+/// 10 bit resolution of the DAC6578
+#[derive(Debug)]
+pub struct Bits10;
+
+impl sealed::Sealed for Bits10 {}
+
+impl Resolution for Bits10 {
+    const BITS: u8 = 10;
+}
+
+/// 12 bit resolution of the DAC7578
+#[derive(Debug)]
+pub struct Bits12;
+
+impl sealed::Sealed for Bits12 {}
+
+impl Resolution for Bits12 {
+    const BITS: u8 = 12;
+}
+
+mod sealed {
+    /// Keeps [`super::Resolution`] from being implemented outside the crate
+    pub trait Sealed {}
+}
+
+/// DAC5578 driver (8 bit)
+pub type DAC5578<I2C, LDAC = NoPin, CLR = NoPin> = DACx578<I2C, Bits8, LDAC, CLR>;
+
+/// DAC6578 driver (10 bit)
+pub type DAC6578<I2C, LDAC = NoPin, CLR = NoPin> = DACx578<I2C, Bits10, LDAC, CLR>;
+
+/// DAC7578 driver (12 bit)
+pub type DAC7578<I2C, LDAC = NoPin, CLR = NoPin> = DACx578<I2C, Bits12, LDAC, CLR>;
+
+/// DACx578 driver. Wraps an I2C port to send commands to a DAC5578, DAC6578 or DAC7578.
+/// Codes are passed in the native resolution of the part (see [`Resolution`]) and are
+/// left-justified by the driver. Codes above the maximum of the part are rejected.
+#[derive(Debug)]
+pub struct DACx578<I2C, R, LDAC = NoPin, CLR = NoPin> {
     i2c: I2C,
-    address: u8,
+    core: Core<R>,
+    ldac: LDAC,
+    clr: CLR,
 }
 
-impl<I2C, E> DAC5578<I2C>
+impl<I2C, R, E> DACx578<I2C, R>
 where
-    I2C: Read<Error = E> + Write<Error = E>,
+    I2C: I2c<Error = E>,
+    R: Resolution,
 {
-    /// Construct a new DAC5578 driver instance.
+    /// Construct a new driver instance.
     /// i2c is the initialized i2c driver port to use, address depends on the state of the ADDR0 pin (see [`Address`])
     pub fn new(i2c: I2C, address: Address) -> Self {
-        DAC5578 {
+        DACx578 {
             i2c,
-            address: address as u8,
+            core: Core::new(address),
+            ldac: NoPin,
+            clr: NoPin,
         }
     }
+}
+
+impl<I2C, R, E, LDAC, CLR> DACx578<I2C, R, LDAC, CLR>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+{
+    /// Use an output pin connected to the LDAC pin, see [`Self::pulse_ldac`].
+    /// The pin should be high, LDAC is active low.
+    pub fn with_ldac<P>(self, ldac: P) -> DACx578<I2C, R, P, CLR> {
+        DACx578 {
+            i2c: self.i2c,
+            core: self.core,
+            ldac,
+            clr: self.clr,
+        }
+    }
+
+    /// Use an output pin connected to the CLR pin, see [`Self::assert_clear`].
+    /// The pin should be high, CLR is active low.
+    pub fn with_clr<P>(self, clr: P) -> DACx578<I2C, R, LDAC, P> {
+        DACx578 {
+            i2c: self.i2c,
+            core: self.core,
+            ldac: self.ldac,
+            clr,
+        }
+    }
+
+    /// Set the voltage applied to VREFIN in microvolts, used by the voltage based methods
+    pub fn set_reference(&mut self, microvolts: u32) {
+        self.core.vref = Some(microvolts);
+    }
+
+    /// The configured reference voltage in microvolts
+    pub fn reference(&self) -> Option<u32> {
+        self.core.vref
+    }
+
+    /// Set the per-channel calibration applied to all codes written to the input registers
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.core.calibration = Some(calibration);
+    }
+
+    /// The configured calibration
+    pub fn calibration(&self) -> Option<&Calibration> {
+        self.core.calibration.as_ref()
+    }
+
+    /// Remove the calibration, returning it
+    pub fn remove_calibration(&mut self) -> Option<Calibration> {
+        self.core.calibration.take()
+    }
+
+    /// Enable the shadow register cache (see [`Cache`]), starting with all registers unknown
+    pub fn enable_cache(&mut self) {
+        self.core.cache = Some(Cache::new());
+    }
+
+    /// Disable the shadow register cache, returning it
+    pub fn disable_cache(&mut self) -> Option<Cache> {
+        self.core.cache.take()
+    }
+
+    /// The shadow register cache, if enabled
+    pub fn cache(&self) -> Option<&Cache> {
+        self.core.cache.as_ref()
+    }
+
+    /// Leave out commands that wouldn't change what the cache knows the device holds.
+    /// Enables the cache if necessary. Use [`Self::force_write`] and
+    /// [`Self::force_write_and_update`] to send a write regardless.
+    pub fn set_skip_redundant(&mut self, skip: bool) {
+        self.core.set_skip_redundant(skip);
+    }
+
+    /// Number of commands left out since the counter was last reset
+    pub fn skipped_writes(&self) -> u32 {
+        self.core.skipped_writes
+    }
+
+    /// Reset the number of commands left out
+    pub fn reset_skipped_writes(&mut self) {
+        self.core.skipped_writes = 0;
+    }
 
     /// Write to the channel's DAC input register
-    pub fn write(&mut self, channel: Channel, data: u16) -> Result<(), E> {
-        let bytes = Self::encode_command(CommandType::WriteToChannel, channel as u8, data);
-        self.i2c.write(self.address, &bytes)
+    pub fn write(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannel, channel, data, false)
     }
 
     /// Selects DAC channel to be updated
-    pub fn update(&mut self, channel: Channel, data: u16) -> Result<(), E> {
-        let bytes = Self::encode_command(CommandType::UpdateChannel, channel as u8, data);
-        self.i2c.write(self.address, &bytes)
+    pub fn update(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        let bytes = command::encode::<R>(CommandType::UpdateChannel, channel as u8, data);
+        self.send(bytes)
     }
 
     /// Write to DAC input register for a channel and update channel DAC register
-    pub fn write_and_update(&mut self, channel: Channel, data: u16) -> Result<(), E> {
-        let bytes = Self::encode_command(CommandType::WriteToChannelAndUpdate, channel as u8, data);
-        self.i2c.write(self.address, &bytes)
+    pub fn write_and_update(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannelAndUpdate, channel, data, false)
+    }
+
+    /// Write to the channel's DAC input register, even if the cache knows it holds the code
+    pub fn force_write(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannel, channel, data, true)
+    }
+
+    /// Write to DAC input register for a channel and update channel DAC register,
+    /// even if the cache knows the channel already outputs the code
+    pub fn force_write_and_update(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(CommandType::WriteToChannelAndUpdate, channel, data, true)
     }
 
     /// Write to Selected DAC Input Register and Update All DAC Registers (Global Software LDAC)
-    pub fn write_and_update_all(&mut self, channel: Channel, data: u16) -> Result<(), E> {
-        let bytes =
-            Self::encode_command(CommandType::WriteToChannelAndUpdateAll, channel as u8, data);
-        self.i2c.write(self.address, &bytes)
+    pub fn write_and_update_all(&mut self, channel: Channel, data: u16) -> Result<(), Error<E>> {
+        self.write_code(
+            CommandType::WriteToChannelAndUpdateAll,
+            channel,
+            data,
+            false,
+        )
+    }
+
+    /// Write each channel of the set to its DAC input register and update all DAC registers
+    /// with the last write, so all outputs change together.
+    /// `codes` holds the codes for channels A to H, codes of channels outside the set are ignored.
+    /// All codes of the set are checked before anything is sent.
+    pub fn write_and_update_channels<C>(
+        &mut self,
+        channels: C,
+        codes: &[u16; 8],
+    ) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        let commands = self.core.write_channels(
+            channels.into(),
+            codes,
+            CommandType::WriteToChannelAndUpdateAll,
+        )?;
+        self.send_all(&commands, false)
+    }
+
+    /// Move the device to the desired state with as few commands as possible,
+    /// returning the number of commands sent.
+    ///
+    /// The target is compared with the shadow register cache, which is enabled if necessary.
+    /// Registers the cache doesn't know are written, unless latching several outputs together
+    /// requires knowing the outputs that aren't targeted; those are read back first.
+    /// All target codes are checked before anything is sent.
+    pub fn apply(&mut self, state: &DesiredState) -> Result<usize, Error<E>> {
+        for channel in self.core.unknown(state)?.iter() {
+            self.read_input(channel)?;
+            self.read_dac(channel)?;
+        }
+        let commands = self.core.plan(state);
+        self.send_all(&commands, false)?;
+        Ok(commands.as_slice().len())
+    }
+
+    /// Write to DAC input register for a channel and update channel DAC register,
+    /// then read the DAC register back to confirm the device holds the code.
+    /// Fails with [`Error::ReadbackMismatch`] if it doesn't and with
+    /// [`Error::InvalidChannel`] for [`Channel::All`].
+    pub fn write_and_update_verified(
+        &mut self,
+        channel: Channel,
+        data: u16,
+    ) -> Result<(), Error<E>> {
+        let expected = self.core.expected(channel, data)?;
+        self.write_and_update(channel, data)?;
+        let actual = self.read_dac(channel)?;
+        if actual != expected {
+            return Err(Error::ReadbackMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Set the channel's output to the given voltage in microvolts.
+    /// Returns the voltage actually set after quantization to the part's resolution.
+    /// Fails with [`Error::NoReference`] if no reference voltage was configured
+    /// with [`Self::set_reference`].
+    pub fn write_and_update_microvolts(
+        &mut self,
+        channel: Channel,
+        microvolts: u32,
+    ) -> Result<u32, Error<E>> {
+        let (code, actual) = self.core.microvolts(microvolts)?;
+        self.write_and_update(channel, code)?;
+        Ok(actual)
+    }
+
+    /// Set the channel's output to the given voltage.
+    /// Returns the voltage actually set after quantization to the part's resolution.
+    /// Fails with [`Error::NoReference`] if no reference voltage was configured
+    /// with [`Self::set_reference`].
+    pub fn write_and_update_volts(
+        &mut self,
+        channel: Channel,
+        volts: f32,
+    ) -> Result<f32, Error<E>> {
+        let microvolts = self.write_and_update_microvolts(channel, (volts * 1e6) as u32)?;
+        Ok(microvolts as f32 / 1e6)
     }
 
     /// Perform a software reset using the selected mode
-    pub fn reset(&mut self, mode: ResetMode) -> Result<(), E> {
-        let bytes = [0x70, mode as u8, 0];
-        self.i2c.write(self.address, &bytes)
+    pub fn reset(&mut self, mode: ResetMode) -> Result<(), Error<E>> {
+        self.send(command::reset(mode))
+    }
+
+    /// Whether the device was put into High-Speed mode, as tracked by the driver across resets
+    pub fn is_high_speed(&self) -> bool {
+        self.core.high_speed
+    }
+
+    /// Start a High-Speed (3.4 MHz) session.
+    ///
+    /// Sends the software reset that puts the device into High-Speed mode and keeps it there
+    /// across STOP conditions. Once this returns, the I2C controller can be switched to its Hs
+    /// clock; the Hs master code in front of every Hs transfer is the controller's job, as a
+    /// master code sent on its own ends with a STOP and returns the bus to F/S mode.
+    /// Resets the device registers. If the bus fails, the device is left in F/S mode.
+    pub fn enter_high_speed(&mut self) -> Result<(), Error<E>> {
+        self.reset(ResetMode::SetHighSpeed)
+    }
+
+    /// End a High-Speed session with a software reset that returns the device to F/S mode.
+    /// Switch the I2C controller back to F/S speed before calling this.
+    /// Resets the device registers, use [`ResetMode::MaintainHighSpeed`] to reset without
+    /// leaving High-Speed mode.
+    pub fn leave_high_speed(&mut self) -> Result<(), Error<E>> {
+        self.reset(ResetMode::Por)
+    }
+
+    /// Power down the given channels, leaving their outputs in the selected mode
+    pub fn power_down<C>(&mut self, channels: C, mode: PowerDownMode) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        self.send(command::power(channels.into(), mode as u8))
+    }
+
+    /// Power up the given channels
+    pub fn power_up<C>(&mut self, channels: C) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        self.send(command::power(channels.into(), command::POWER_UP))
+    }
+
+    /// Set the code the outputs are cleared to when the CLR pin is asserted
+    pub fn set_clear_code(&mut self, code: ClearCode) -> Result<(), Error<E>> {
+        self.send(command::clear_code(code))
+    }
+
+    /// Set which channels ignore the hardware LDAC pin.
+    /// Masked channels are only updated by software, all other channels latch on the LDAC pin.
+    pub fn set_ldac_mask<C>(&mut self, channels: C) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        self.send(command::ldac(channels.into()))
+    }
+
+    /// Read back the channel's DAC input register
+    pub fn read_input(&mut self, channel: Channel) -> Result<u16, Error<E>> {
+        self.read_register(Register::Input, channel)
+    }
+
+    /// Read back the channel's DAC register
+    pub fn read_dac(&mut self, channel: Channel) -> Result<u16, Error<E>> {
+        self.read_register(Register::Dac, channel)
+    }
+
+    /// Read back a register for a channel.
+    /// Sends the command byte followed by a repeated-start read of the two data bytes.
+    /// Fails with [`Error::InvalidChannel`] for [`Channel::All`].
+    pub fn read_register(&mut self, register: Register, channel: Channel) -> Result<u16, Error<E>> {
+        let bytes = self.read_raw(self.core.read(register, channel)?)?;
+        Ok(self.core.register_read(register, channel, bytes))
+    }
+
+    /// Read back the code the outputs are cleared to when the CLR pin is asserted
+    pub fn read_clear_code(&mut self) -> Result<ClearCode, Error<E>> {
+        let bytes = self.read_raw(CommandType::ClearCode as u8)?;
+        Ok(self.core.clear_code_read(bytes))
+    }
+
+    /// Read back the LDAC register, i.e. the channels that ignore the hardware LDAC pin
+    pub fn read_ldac_mask(&mut self) -> Result<ChannelSet, Error<E>> {
+        let bytes = self.read_raw(CommandType::Ldac as u8)?;
+        Ok(self.core.ldac_mask_read(bytes))
     }
 
     /// Send a wake-up command over the I2C bus.
     /// WARNING: This is a general call command and can wake-up other devices on the bus as well.
-    pub fn wake_up_all(&mut self) -> Result<(), E> {
-        self.i2c.write(0x00, &[0x06u8])?;
-        Ok(())
+    pub fn wake_up_all(&mut self) -> Result<(), Error<E>> {
+        self.send_general_call(&command::WAKE_UP)
     }
 
     /// Send a reset command on the I2C bus.
     /// WARNING: This is a general call command and can reset other devices on the bus as well.
-    pub fn reset_all(&mut self) -> Result<(), E> {
-        self.i2c.write(0x00, &[0x09u8])?;
-        Ok(())
+    pub fn reset_all(&mut self) -> Result<(), Error<E>> {
+        self.send_general_call(&command::RESET)
     }
 
-    /// Destroy the DAC5578 driver, return the wrapped I2C
+    /// Destroy the driver, return the wrapped I2C
     pub fn destroy(self) -> I2C {
         self.i2c
     }
 
-    /// Encode command type, channel and data into a three byte command
-    fn encode_command(command: CommandType, access: u8, value: u16) -> [u8; 3] {
-        let value_bytes = value.to_be_bytes();
-        [command as u8 | access, value_bytes[0], value_bytes[1]]
+    /// Destroy the driver, return the wrapped I2C and the LDAC and CLR pins
+    pub fn release(self) -> (I2C, LDAC, CLR) {
+        (self.i2c, self.ldac, self.clr)
+    }
+
+    /// Send a command writing a code to the channel's input register, applying the calibration.
+    /// Fails with [`Error::CodeOutOfRange`] for codes above the maximum of the part.
+    fn write_code(
+        &mut self,
+        command: CommandType,
+        channel: Channel,
+        code: u16,
+        force: bool,
+    ) -> Result<(), Error<E>> {
+        let commands = self.core.write(command, channel, code)?;
+        self.send_all(&commands, force)
+    }
+
+    /// Write to DAC input register for a channel and update channel DAC register with a code
+    /// the calibration was already applied to, e.g. one read back from the device
+    pub(crate) fn write_and_update_uncalibrated(
+        &mut self,
+        channel: Channel,
+        code: u16,
+    ) -> Result<(), Error<E>> {
+        let bytes =
+            self.core
+                .write_uncalibrated(CommandType::WriteToChannelAndUpdate, channel, code)?;
+        self.send(bytes)
+    }
+
+    /// Send a sequence of three byte commands to the device
+    fn send_all(&mut self, commands: &Commands, force: bool) -> Result<(), Error<E>> {
+        for bytes in commands.as_slice() {
+            self.transmit(*bytes, force)?;
+        }
+        Ok(())
+    }
+
+    /// Send a three byte command to the device unless it is redundant
+    fn send(&mut self, bytes: [u8; 3]) -> Result<(), Error<E>> {
+        self.transmit(bytes, false)
+    }
+
+    /// Send a three byte command to the device and record it in the cache.
+    /// Unless forced, redundant commands are left out if enabled with [`Self::set_skip_redundant`].
+    fn transmit(&mut self, bytes: [u8; 3], force: bool) -> Result<(), Error<E>> {
+        if self.core.skip(&bytes, force) {
+            return Ok(());
+        }
+        self.i2c
+            .write(self.core.address, &bytes)
+            .map_err(Error::I2c)?;
+        self.core.sent(&bytes);
+        Ok(())
+    }
+
+    /// Send a general call command and record it in the cache
+    fn send_general_call(&mut self, bytes: &[u8; 1]) -> Result<(), Error<E>> {
+        self.i2c
+            .write(command::GENERAL_CALL_ADDRESS, bytes)
+            .map_err(Error::I2c)?;
+        self.core.general_call_sent(bytes);
+        Ok(())
+    }
+
+    /// Send the command byte and read the two data bytes after a repeated start
+    fn read_raw(&mut self, command: u8) -> Result<[u8; 2], Error<E>> {
+        let mut buffer = [0u8; 2];
+        self.i2c
+            .write_read(self.core.address, &[command], &mut buffer)
+            .map_err(Error::I2c)?;
+        Ok(buffer)
     }
 }
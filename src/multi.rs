@@ -0,0 +1,319 @@
+//! Several DACx578 on one I2C bus, driven as a single array of channels.
+//!
+//! A [`MultiDAC`] owns the bus and up to three devices, one per [`Address`]. Their channels are
+//! numbered in the order of the addresses, so with three devices channel 0 is channel A of the
+//! first device and channel 23 is channel H of the last one. Synchronized updates write all
+//! codes to the input registers first and latch them afterwards, either with one update
+//! command per device right after each other or with a pulse of an LDAC line shared by all
+//! devices. General calls are sent once for the whole bus.
+//!
+//! Every device keeps its own configuration and tracked state, like the calibration, the
+//! reference voltage and the shadow register cache. They are set through [`MultiDAC::device`]
+//! and apply to the writes and reads by channel index as well.
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use dac5578::multi::MultiDAC5578;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x00, 0x14, 0x00]),
+//! #     Transaction::write(0x4a, vec![0x01, 0x14, 0x00]),
+//! #     Transaction::write(0x4c, vec![0x07, 0x1e, 0x00]),
+//! #     Transaction::write(0x48, vec![0x1f, 0x00, 0x00]),
+//! #     Transaction::write(0x4a, vec![0x1f, 0x00, 0x00]),
+//! #     Transaction::write(0x4c, vec![0x1f, 0x00, 0x00]),
+//! #     Transaction::write(0x4a, vec![0x40, 0x20, 0x20]),
+//! #     Transaction::write(0x00, vec![0x09]),
+//! # ]);
+//! use dac5578::calibration::{Calibration, ChannelCalibration, UNITY_GAIN};
+//!
+//! let mut dacs = MultiDAC5578::new(i2c, [Address::PinLow, Address::PinHigh, Address::PinFloat]);
+//! assert_eq!(dacs.channels(), 24);
+//! assert_eq!(dacs.locate(9), Some((Address::PinHigh, Channel::B)));
+//!
+//! // Channel A of the first device only reaches half the output span
+//! let mut calibration = Calibration::new();
+//! calibration.set_channel(Channel::A, ChannelCalibration::new(UNITY_GAIN * 2, 0));
+//! dacs.device(Address::PinLow).unwrap().set_calibration(calibration);
+//!
+//! let mut codes = [None; 24];
+//! codes[0] = Some(10);
+//! codes[9] = Some(20);
+//! codes[23] = Some(30);
+//! dacs.write_and_update_channels(&codes).unwrap();
+//!
+//! // Device specific commands go through a driver borrowing the bus
+//! dacs.device(Address::PinHigh)
+//!     .unwrap()
+//!     .power_down(Channel::A, PowerDownMode::Pulldown1K)
+//!     .unwrap();
+//!
+//! dacs.reset_all().unwrap();
+//! # dacs.destroy().done();
+//! ```
+
+use core::convert::TryFrom;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+
+use crate::driver::Core;
+use crate::pins::pin_error;
+use crate::{command, Address, Bits10, Bits12, Bits8, Channel, DACx578, Error, NoPin, Resolution};
+
+/// Several DAC5578 (8 bit) on one bus
+pub type MultiDAC5578<I2C, const N: usize, LDAC = NoPin> = MultiDAC<I2C, Bits8, N, LDAC>;
+
+/// Several DAC6578 (10 bit) on one bus
+pub type MultiDAC6578<I2C, const N: usize, LDAC = NoPin> = MultiDAC<I2C, Bits10, N, LDAC>;
+
+/// Several DAC7578 (12 bit) on one bus
+pub type MultiDAC7578<I2C, const N: usize, LDAC = NoPin> = MultiDAC<I2C, Bits12, N, LDAC>;
+
+/// Owns an I2C bus with `N` devices of the same resolution, exposing their `8 * N` channels
+#[derive(Debug)]
+pub struct MultiDAC<I2C, R, const N: usize, LDAC = NoPin> {
+    i2c: I2C,
+    addresses: [Address; N],
+    cores: [Core<R>; N],
+    ldac: LDAC,
+}
+
+/// Driver for one of the devices of a [`MultiDAC`], borrowing the bus and the state of the
+/// device. Settings made through it are kept when it is dropped.
+#[derive(Debug)]
+pub struct Device<'a, I2C, R> {
+    dac: DACx578<&'a mut I2C, R>,
+    core: &'a mut Core<R>,
+}
+
+impl<I2C, R, E, const N: usize> MultiDAC<I2C, R, N>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+{
+    /// Take the bus and the addresses of the devices on it, in the order of their channels.
+    ///
+    /// # Panics
+    /// Panics if an address is given twice.
+    pub fn new(i2c: I2C, addresses: [Address; N]) -> Self {
+        for (index, address) in addresses.iter().enumerate() {
+            assert!(
+                !addresses[..index].contains(address),
+                "address {:?} is given twice",
+                address
+            );
+        }
+        MultiDAC {
+            i2c,
+            addresses,
+            cores: addresses.map(Core::new),
+            ldac: NoPin,
+        }
+    }
+}
+
+impl<I2C, R, E, const N: usize, LDAC> MultiDAC<I2C, R, N, LDAC>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+{
+    /// Use an output pin connected to the LDAC pins of all devices,
+    /// see [`Self::write_and_latch_channels`]. The pin should be high, LDAC is active low.
+    pub fn with_ldac<P>(self, ldac: P) -> MultiDAC<I2C, R, N, P> {
+        MultiDAC {
+            i2c: self.i2c,
+            addresses: self.addresses,
+            cores: self.cores,
+            ldac,
+        }
+    }
+
+    /// Number of channels of all devices
+    pub fn channels(&self) -> usize {
+        N * 8
+    }
+
+    /// Addresses of the devices, in the order of their channels
+    pub fn addresses(&self) -> &[Address; N] {
+        &self.addresses
+    }
+
+    /// Device and channel of a channel index, `None` if the index is out of range
+    pub fn locate(&self, index: usize) -> Option<(Address, Channel)> {
+        let address = *self.addresses.get(index / 8)?;
+        let channel = Channel::try_from((index % 8) as u8).ok()?;
+        Some((address, channel))
+    }
+
+    /// Driver for one of the devices, borrowing the bus. `None` if the address isn't managed.
+    /// The device keeps its configuration and tracked state between calls.
+    ///
+    /// ```
+    /// # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    /// # use dac5578::*;
+    /// use dac5578::multi::MultiDAC5578;
+    /// # let mut i2c = Mock::new(&[
+    /// #     Transaction::write(0x4a, vec![0x33, 0x80, 0x00]),
+    /// # ]);
+    /// let mut dacs = MultiDAC5578::new(i2c, [Address::PinLow, Address::PinHigh]);
+    /// dacs.device(Address::PinHigh).unwrap().enable_cache();
+    /// dacs.write_and_update(11, 0x80).unwrap();
+    ///
+    /// let dac = dacs.device(Address::PinHigh).unwrap();
+    /// assert_eq!(dac.cache().unwrap().dac(Channel::D), Some(0x80));
+    /// # drop(dac);
+    /// # dacs.destroy().done();
+    /// ```
+    pub fn device(&mut self, address: Address) -> Option<Device<'_, I2C, R>> {
+        let index = self.addresses.iter().position(|a| *a == address)?;
+        let core = &mut self.cores[index];
+        let dac = DACx578 {
+            i2c: &mut self.i2c,
+            core: mem::replace(core, Core::new(address)),
+            ldac: NoPin,
+            clr: NoPin,
+        };
+        Some(Device { dac, core })
+    }
+
+    /// Write to the DAC input register of the channel at the index
+    pub fn write(&mut self, index: usize, code: u16) -> Result<(), Error<E>> {
+        let (mut dac, channel) = self.channel(index)?;
+        dac.write(channel, code)
+    }
+
+    /// Write to the DAC input register of the channel at the index and update its DAC register
+    pub fn write_and_update(&mut self, index: usize, code: u16) -> Result<(), Error<E>> {
+        let (mut dac, channel) = self.channel(index)?;
+        dac.write_and_update(channel, code)
+    }
+
+    /// Read back the DAC input register of the channel at the index
+    pub fn read_input(&mut self, index: usize) -> Result<u16, Error<E>> {
+        let (mut dac, channel) = self.channel(index)?;
+        dac.read_input(channel)
+    }
+
+    /// Read back the DAC register of the channel at the index
+    pub fn read_dac(&mut self, index: usize) -> Result<u16, Error<E>> {
+        let (mut dac, channel) = self.channel(index)?;
+        dac.read_dac(channel)
+    }
+
+    /// Write the codes to the input registers, then update every device that got a code with
+    /// one command each, right after each other. `codes` holds a code or `None` per channel
+    /// index. All codes are checked before anything is sent.
+    /// Fails with [`Error::InvalidChannel`] if there are more codes than channels.
+    pub fn write_and_update_channels(&mut self, codes: &[Option<u16>]) -> Result<(), Error<E>> {
+        let written = self.stage(codes)?;
+        for (index, _) in written.iter().enumerate().filter(|(_, w)| **w) {
+            self.device_at(index).update(Channel::All, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Send a wake-up command on the I2C bus, waking all devices at once.
+    /// WARNING: This is a general call command and can wake-up other devices on the bus as well.
+    pub fn wake_up_all(&mut self) -> Result<(), Error<E>> {
+        self.send_general_call(&command::WAKE_UP)
+    }
+
+    /// Send a reset command on the I2C bus, resetting all devices at once.
+    /// WARNING: This is a general call command and can reset other devices on the bus as well.
+    pub fn reset_all(&mut self) -> Result<(), Error<E>> {
+        self.send_general_call(&command::RESET)
+    }
+
+    /// Destroy the manager, return the wrapped I2C
+    pub fn destroy(self) -> I2C {
+        self.i2c
+    }
+
+    /// Destroy the manager, return the wrapped I2C and the LDAC pin
+    pub fn release(self) -> (I2C, LDAC) {
+        (self.i2c, self.ldac)
+    }
+
+    /// Driver for the device of the channel at the index and its channel on that device
+    fn channel(&mut self, index: usize) -> Result<(Device<'_, I2C, R>, Channel), Error<E>> {
+        let channel = self.locate(index).ok_or(Error::InvalidChannel)?.1;
+        Ok((self.device_at(index / 8), channel))
+    }
+
+    /// Driver for the device at the position of its address
+    fn device_at(&mut self, position: usize) -> Device<'_, I2C, R> {
+        let address = self.addresses[position];
+        self.device(address).expect("the address is managed")
+    }
+
+    /// Send a general call command and record it for every device
+    fn send_general_call(&mut self, bytes: &[u8; 1]) -> Result<(), Error<E>> {
+        self.i2c
+            .write(command::GENERAL_CALL_ADDRESS, bytes)
+            .map_err(Error::I2c)?;
+        for core in self.cores.iter_mut() {
+            core.general_call_sent(bytes);
+        }
+        Ok(())
+    }
+
+    /// Write the codes to the input registers, returning which devices got a code
+    fn stage(&mut self, codes: &[Option<u16>]) -> Result<[bool; N], Error<E>> {
+        if codes.len() > self.channels() {
+            return Err(Error::InvalidChannel);
+        }
+        if let Some(code) = codes.iter().flatten().find(|&&code| code > R::MAX_CODE) {
+            return Err(Error::CodeOutOfRange(*code));
+        }
+        let mut written = [false; N];
+        for (index, code) in codes.iter().enumerate() {
+            if let (Some(code), Some((_, channel))) = (code, self.locate(index)) {
+                self.device_at(index / 8).write(channel, *code)?;
+                written[index / 8] = true;
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl<I2C, R, E, const N: usize, LDAC> MultiDAC<I2C, R, N, LDAC>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+    LDAC: OutputPin,
+{
+    /// Write the codes to the input registers, then pulse the shared LDAC line so the outputs
+    /// of all devices change at the same time. Channels masked with
+    /// [`DACx578::set_ldac_mask`] ignore the pulse. `codes` holds a code or `None` per channel
+    /// index. All codes are checked before anything is sent.
+    /// Fails with [`Error::InvalidChannel`] if there are more codes than channels.
+    pub fn write_and_latch_channels(&mut self, codes: &[Option<u16>]) -> Result<(), Error<E>> {
+        self.stage(codes)?;
+        self.ldac.set_low().map_err(pin_error)?;
+        self.ldac.set_high().map_err(pin_error)
+    }
+}
+
+impl<'a, I2C, R> Deref for Device<'a, I2C, R> {
+    type Target = DACx578<&'a mut I2C, R>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.dac
+    }
+}
+
+impl<I2C, R> DerefMut for Device<'_, I2C, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.dac
+    }
+}
+
+impl<I2C, R> Drop for Device<'_, I2C, R> {
+    fn drop(&mut self) {
+        // Hand the state back to the manager
+        mem::swap(self.core, &mut self.dac.core);
+    }
+}
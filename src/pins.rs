@@ -0,0 +1,106 @@
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+
+use crate::driver::Core;
+use crate::{ChannelSet, CommandType, DACx578, Error, Resolution};
+
+/// Placeholder for an LDAC or CLR pin that isn't controlled by the driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NoPin;
+
+impl<I2C, R, E, LDAC, CLR> DACx578<I2C, R, LDAC, CLR>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+    LDAC: OutputPin,
+{
+    /// Pulse the LDAC pin, latching the input registers of all channels that aren't masked
+    /// with [`Self::set_ldac_mask`] into their DAC registers at once
+    pub fn pulse_ldac(&mut self) -> Result<(), Error<E>> {
+        pulse_ldac(&mut self.ldac, &mut self.core)
+    }
+
+    /// Write each channel of the set to its DAC input register, then pulse the LDAC pin
+    /// so all outputs change together without a software update command.
+    /// `codes` holds the codes for channels A to H, codes of channels outside the set are ignored.
+    /// All codes of the set are checked before anything is sent.
+    ///
+    /// ```
+    /// # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    /// use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction as PinTransaction};
+    /// # use dac5578::*;
+    /// # let mut i2c = Mock::new(&[
+    /// #     Transaction::write(0x48, vec![0x00, 0x0a, 0x00]),
+    /// #     Transaction::write(0x48, vec![0x03, 0x28, 0x00]),
+    /// # ]);
+    /// let ldac = PinMock::new(&[PinTransaction::set(State::Low), PinTransaction::set(State::High)]);
+    /// let mut dac = DAC5578::new(i2c, Address::PinLow).with_ldac(ldac);
+    /// let mut codes = [0; 8];
+    /// codes[Channel::A as usize] = 10;
+    /// codes[Channel::D as usize] = 40;
+    /// dac.write_and_latch_channels([Channel::A, Channel::D], &codes).unwrap();
+    /// # let (mut i2c, mut ldac, _) = dac.release();
+    /// # i2c.done();
+    /// # ldac.done();
+    /// ```
+    pub fn write_and_latch_channels<C>(
+        &mut self,
+        channels: C,
+        codes: &[u16; 8],
+    ) -> Result<(), Error<E>>
+    where
+        C: Into<ChannelSet>,
+    {
+        let commands =
+            self.core
+                .write_channels(channels.into(), codes, CommandType::WriteToChannel)?;
+        self.send_all(&commands, false)?;
+        self.pulse_ldac()
+    }
+}
+
+impl<I2C, R, E, LDAC, CLR> DACx578<I2C, R, LDAC, CLR>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+    CLR: OutputPin,
+{
+    /// Assert the CLR pin, loading the clear code (see [`Self::set_clear_code`]) into the
+    /// input and DAC registers of all channels
+    pub fn assert_clear(&mut self) -> Result<(), Error<E>> {
+        assert_clear(&mut self.clr, &mut self.core)
+    }
+
+    /// Release the CLR pin
+    pub fn release_clear(&mut self) -> Result<(), Error<E>> {
+        self.clr.set_high().map_err(pin_error)
+    }
+}
+
+/// Wrap the error of an output pin
+pub(crate) fn pin_error<P: embedded_hal::digital::Error, E>(error: P) -> Error<E> {
+    Error::Pin(error.kind())
+}
+
+/// Pulse the LDAC pin and record the latched outputs
+pub(crate) fn pulse_ldac<P, R, E>(ldac: &mut P, core: &mut Core<R>) -> Result<(), Error<E>>
+where
+    P: OutputPin,
+    R: Resolution,
+{
+    ldac.set_low().map_err(pin_error)?;
+    ldac.set_high().map_err(pin_error)?;
+    core.ldac_pulsed();
+    Ok(())
+}
+
+/// Assert the CLR pin and record the cleared outputs
+pub(crate) fn assert_clear<P, R, E>(clr: &mut P, core: &mut Core<R>) -> Result<(), Error<E>>
+where
+    P: OutputPin,
+    R: Resolution,
+{
+    clr.set_low().map_err(pin_error)?;
+    core.cleared();
+    Ok(())
+}
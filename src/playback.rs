@@ -0,0 +1,258 @@
+//! Playback of recorded sample buffers at a fixed rate.
+//!
+//! A [`Player`] plays a buffer of frames, each holding one code per channel of a
+//! [`ChannelSet`] in the order A to H. All channels of a frame are written to their input
+//! registers and latched together by the last write. Playback either runs blocking with an
+//! embedded-hal [`DelayNs`] ([`Player::play`]) or is driven by a timer: [`Player::tick`] plays
+//! one frame per call, and [`Player::poll`] plays the frame due at a free-running tick count,
+//! skipping frames when the writes fall behind.
+//!
+//! Frames skipped that way are counted as overruns. Frames due after a one-shot buffer has ended
+//! are counted as underruns.
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use dac5578::playback::{Mode, Player, Status};
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x00, 0x00, 0x00]),
+//! #     Transaction::write(0x48, vec![0x21, 0xff, 0x00]),
+//! #     Transaction::write(0x48, vec![0x00, 0xff, 0x00]),
+//! #     Transaction::write(0x48, vec![0x21, 0x00, 0x00]),
+//! #     Transaction::write(0x48, vec![0x00, 0x80, 0x00]),
+//! #     Transaction::write(0x48, vec![0x21, 0x80, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! // Frames of channel A and B
+//! let frames = [0, 255, 128, 128, 255, 0];
+//! let mut player = Player::new(&frames, Channel::A | Channel::B, Mode::PingPong);
+//!
+//! // Driven by a timer counting ticks. The frame due at tick 11 is skipped, and ping-pong
+//! // playback turns around at the last frame.
+//! assert_eq!(player.poll(&mut dac, 10).unwrap(), Status::Playing);
+//! assert_eq!(player.poll(&mut dac, 10).unwrap(), Status::Playing);
+//! assert_eq!(player.poll(&mut dac, 12).unwrap(), Status::Playing);
+//! assert_eq!(player.overruns(), 1);
+//! assert_eq!(player.position(), 1);
+//! player.tick(&mut dac).unwrap();
+//! # dac.destroy().done();
+//! ```
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+use crate::{ChannelSet, DACx578, Error, Resolution};
+
+/// What happens at the end of the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Play the buffer once, the outputs stay at the last frame
+    OneShot,
+    /// Start over at the first frame
+    Loop,
+    /// Play the buffer backwards, then forwards again, without repeating the first and last frame
+    PingPong,
+}
+
+/// State of the player after playing a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// There are more frames to play
+    Playing,
+    /// The one-shot buffer has ended
+    Finished,
+}
+
+/// Player for a buffer of frames
+#[derive(Debug)]
+pub struct Player<'a> {
+    frames: &'a [u16],
+    channels: ChannelSet,
+    mode: Mode,
+    position: usize,
+    backwards: bool,
+    finished: bool,
+    last_tick: Option<u32>,
+    overruns: u32,
+    underruns: u32,
+}
+
+impl<'a> Player<'a> {
+    /// Create a player for the frames of the channels, starting at the first frame.
+    /// `frames` holds one code per channel of the set for every frame, in the order A to H.
+    ///
+    /// # Panics
+    /// Panics if the set is empty or the buffer doesn't hold whole frames.
+    pub fn new(frames: &'a [u16], channels: ChannelSet, mode: Mode) -> Self {
+        assert!(!channels.is_empty(), "no channels to play");
+        assert!(
+            frames.chunks_exact(channels.len()).remainder().is_empty(),
+            "buffer doesn't hold whole frames"
+        );
+        Player {
+            frames,
+            channels,
+            mode,
+            position: 0,
+            backwards: false,
+            finished: frames.is_empty(),
+            last_tick: None,
+            overruns: 0,
+            underruns: 0,
+        }
+    }
+
+    /// Number of frames in the buffer
+    pub fn len(&self) -> usize {
+        self.frames.len() / self.channels.len()
+    }
+
+    /// Whether the buffer holds no frames
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Index of the frame played next
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Frames skipped by [`Self::poll`] because they were due before the previous one was played
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+
+    /// Frames due after a one-shot buffer has ended
+    pub fn underruns(&self) -> u32 {
+        self.underruns
+    }
+
+    /// Start over at the first frame and reset the overrun and underrun counters
+    pub fn rewind(&mut self) {
+        self.position = 0;
+        self.backwards = false;
+        self.finished = self.frames.is_empty();
+        self.last_tick = None;
+        self.overruns = 0;
+        self.underruns = 0;
+    }
+
+    /// Play the next frame. Call this at the frame rate, e.g. from a timer interrupt.
+    pub fn tick<I2C, R, E, LDAC, CLR>(
+        &mut self,
+        dac: &mut DACx578<I2C, R, LDAC, CLR>,
+    ) -> Result<Status, Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        R: Resolution,
+    {
+        if self.finished {
+            self.underruns = self.underruns.wrapping_add(1);
+            return Ok(Status::Finished);
+        }
+        let mut codes = [0; 8];
+        let frame = &self.frames[self.position * self.channels.len()..];
+        for (channel, &code) in self.channels.iter().zip(frame) {
+            codes[channel as usize] = code;
+        }
+        dac.write_and_update_channels(self.channels, &codes)?;
+        self.advance();
+        Ok(self.status())
+    }
+
+    /// Play the frame due at the tick count of a free-running timer, one frame per tick.
+    /// Nothing is played if the count hasn't changed since the last frame. If it advanced by
+    /// more than one, the frames in between are skipped and counted as overruns.
+    pub fn poll<I2C, R, E, LDAC, CLR>(
+        &mut self,
+        dac: &mut DACx578<I2C, R, LDAC, CLR>,
+        now: u32,
+    ) -> Result<Status, Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        R: Resolution,
+    {
+        if let Some(last) = self.last_tick {
+            let elapsed = now.wrapping_sub(last);
+            if elapsed == 0 {
+                return Ok(self.status());
+            }
+            self.skip(elapsed - 1);
+        }
+        self.last_tick = Some(now);
+        self.tick(dac)
+    }
+
+    /// Play up to `frames` frames, waiting `period_us` between them, and return the number of
+    /// frames played. Stops early at the end of a one-shot buffer.
+    /// The time to write a frame adds to the period, use [`Self::poll`] for exact timing.
+    pub fn play<I2C, R, E, LDAC, CLR>(
+        &mut self,
+        dac: &mut DACx578<I2C, R, LDAC, CLR>,
+        delay: &mut impl DelayNs,
+        period_us: u32,
+        frames: usize,
+    ) -> Result<usize, Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        R: Resolution,
+    {
+        for played in 0..frames {
+            if self.finished {
+                return Ok(played);
+            }
+            self.tick(dac)?;
+            delay.delay_us(period_us);
+        }
+        Ok(frames)
+    }
+
+    /// Skip frames that were due before the current one
+    fn skip(&mut self, frames: u32) {
+        let len = self.len() as u32;
+        let (skipped, steps) = match self.mode {
+            _ if self.finished => (0, 0),
+            Mode::OneShot => {
+                let skipped = frames.min(len - self.position as u32);
+                (skipped, skipped)
+            }
+            Mode::Loop => (frames, frames % len),
+            Mode::PingPong => (frames, frames % (2 * len).saturating_sub(2).max(1)),
+        };
+        self.overruns = self.overruns.wrapping_add(skipped);
+        self.underruns = self.underruns.wrapping_add(frames - skipped);
+        for _ in 0..steps {
+            self.advance();
+        }
+    }
+
+    /// Status after the current frame
+    fn status(&self) -> Status {
+        if self.finished {
+            Status::Finished
+        } else {
+            Status::Playing
+        }
+    }
+
+    /// Move to the next frame according to the mode
+    fn advance(&mut self) {
+        let last = self.len() - 1;
+        match self.mode {
+            Mode::OneShot if self.position == last => self.finished = true,
+            Mode::Loop if self.position == last => self.position = 0,
+            Mode::OneShot | Mode::Loop => self.position += 1,
+            Mode::PingPong if last == 0 => {}
+            Mode::PingPong if self.backwards && self.position == 0 => {
+                self.backwards = false;
+                self.position = 1;
+            }
+            Mode::PingPong if self.backwards => self.position -= 1,
+            Mode::PingPong if self.position == last => {
+                self.backwards = true;
+                self.position = last - 1;
+            }
+            Mode::PingPong => self.position += 1,
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Ramps between setpoints, for outputs that must not see step changes.
+//!
+//! A [`Ramp`] moves a channel from its current code to a target, one step per tick, either
+//! over a number of ticks or limited to a maximum number of codes per tick (see [`Speed`]).
+//! The steps follow a linear or S-curve [`Profile`]. [`Ramp::from_current`] starts at the code
+//! the shadow register cache knows, or reads it back from the device. The ramp is driven by
+//! [`Ramp::poll`], e.g. from a timer interrupt, or blocking with [`Ramp::run`].
+//!
+//! Ramps run in the codes the device holds. With a calibration set, the target is corrected once
+//! when the ramp is created and the steps are written without correcting them again.
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use dac5578::ramp::{Profile, Ramp, Speed};
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write_read(0x48, vec![0x10], vec![0x0a, 0x00]),
+//! #     Transaction::write(0x48, vec![0x30, 0x23, 0x00]),
+//! #     Transaction::write(0x48, vec![0x30, 0x3c, 0x00]),
+//! #     Transaction::write(0x48, vec![0x30, 0x55, 0x00]),
+//! #     Transaction::write(0x48, vec![0x30, 0x6e, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! // Reads back the current code, 10, and moves by at most 25 codes per tick
+//! let mut ramp = Ramp::from_current(&mut dac, Channel::A, 110, Speed::Rate(25), Profile::Linear)
+//!     .unwrap();
+//! while !ramp.poll(&mut dac).unwrap() {}
+//!
+//! let codes: Vec<u16> = Ramp::new(Channel::A, 0, 100, Speed::Ticks(4), Profile::SCurve).collect();
+//! assert_eq!(codes, [16, 50, 84, 100]);
+//! # dac.destroy().done();
+//! ```
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+use crate::{Channel, DACx578, Error, Resolution};
+
+/// Shape of a ramp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Constant rate of change
+    Linear,
+    /// Smooth start and end (smoothstep), with the steepest change halfway
+    SCurve,
+}
+
+/// How fast a ramp moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    /// Reach the target after this many ticks
+    Ticks(u32),
+    /// Change by at most this many codes per tick
+    Rate(u16),
+}
+
+/// Ramp of a channel from one code to another, yielding the code of every tick
+#[derive(Debug, Clone)]
+pub struct Ramp {
+    channel: Channel,
+    from: u16,
+    to: u16,
+    steps: u32,
+    step: u32,
+    profile: Profile,
+    rate: Option<u16>,
+    last: Option<u16>,
+}
+
+impl Ramp {
+    /// Create a ramp of the channel between two codes the device holds, i.e. with the
+    /// calibration already applied. The codes of the ticks are rounded to the nearest code,
+    /// and with [`Speed::Rate`] no tick changes the code by more than the rate.
+    ///
+    /// ```
+    /// use dac5578::ramp::{Profile, Ramp, Speed};
+    /// use dac5578::Channel;
+    ///
+    /// let codes: Vec<u16> = Ramp::new(Channel::A, 0, 195, Speed::Rate(39), Profile::Linear).collect();
+    /// assert_eq!(codes, [39, 78, 117, 156, 195]);
+    ///
+    /// for profile in [Profile::Linear, Profile::SCurve] {
+    ///     for (to, rate) in [(195, 39), (36, 1), (1000, 7)] {
+    ///         let ramp = Ramp::new(Channel::A, 0, to, Speed::Rate(rate), profile);
+    ///         let codes: Vec<u16> = core::iter::once(0).chain(ramp).collect();
+    ///         let largest = codes.windows(2).map(|pair| pair[0].abs_diff(pair[1])).max();
+    ///         assert!(largest.unwrap() <= rate);
+    ///         assert_eq!(codes.last(), Some(&to));
+    ///     }
+    /// }
+    /// ```
+    pub fn new(channel: Channel, from: u16, to: u16, speed: Speed, profile: Profile) -> Self {
+        let distance = (to as i32 - from as i32).unsigned_abs();
+        let steps = match (speed, profile) {
+            (Speed::Ticks(ticks), _) => ticks,
+            (Speed::Rate(rate), Profile::Linear) => distance.div_ceil(rate.max(1) as u32),
+            // The steepest slope of the S-curve is 1.5 times the average
+            (Speed::Rate(rate), Profile::SCurve) => (3 * distance).div_ceil(2 * rate.max(1) as u32),
+        };
+        Ramp {
+            channel,
+            from,
+            to,
+            steps: steps.max(1),
+            step: 0,
+            profile,
+            rate: match speed {
+                Speed::Ticks(_) => None,
+                Speed::Rate(rate) => Some(rate.max(1)),
+            },
+            last: None,
+        }
+    }
+
+    /// Create a ramp of the channel from its current code to the target.
+    /// The current code is taken from the cache if it knows it, otherwise it is read back.
+    /// The target is corrected with the calibration like the code of any other write.
+    /// Fails with [`Error::InvalidChannel`] for [`Channel::All`].
+    ///
+    /// ```
+    /// # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    /// # use dac5578::*;
+    /// use dac5578::calibration::{Calibration, ChannelCalibration, UNITY_GAIN};
+    /// use dac5578::ramp::{Profile, Ramp, Speed};
+    /// # let mut i2c = Mock::new(&[
+    /// #     Transaction::write(0x48, vec![0x30, 0x64, 0x00]),
+    /// #     Transaction::write(0x48, vec![0x30, 0x6e, 0x00]),
+    /// #     Transaction::write(0x48, vec![0x30, 0x78, 0x00]),
+    /// # ]);
+    /// # let mut dac = DAC5578::new(i2c, Address::PinLow);
+    /// let mut calibration = Calibration::new();
+    /// calibration.set_channel(Channel::A, ChannelCalibration::new(UNITY_GAIN / 2, 0));
+    /// dac.set_calibration(calibration);
+    /// dac.enable_cache();
+    /// // The device holds 100
+    /// dac.write_and_update(Channel::A, 200).unwrap();
+    ///
+    /// let mut ramp = Ramp::from_current(&mut dac, Channel::A, 240, Speed::Rate(10), Profile::Linear)
+    ///     .unwrap();
+    /// assert_eq!(ramp.target(), 120);
+    /// // The first tick moves the output by no more than 10 codes
+    /// ramp.poll(&mut dac).unwrap();
+    /// assert_eq!(dac.cache().unwrap().dac(Channel::A), Some(110));
+    /// while !ramp.poll(&mut dac).unwrap() {}
+    /// # dac.destroy().done();
+    /// ```
+    pub fn from_current<I2C, R, E, LDAC, CLR>(
+        dac: &mut DACx578<I2C, R, LDAC, CLR>,
+        channel: Channel,
+        to: u16,
+        speed: Speed,
+        profile: Profile,
+    ) -> Result<Self, Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        R: Resolution,
+    {
+        if to > R::MAX_CODE {
+            return Err(Error::CodeOutOfRange(to));
+        }
+        let to = dac.core.expected(channel, to)?;
+        let from = match dac.cache().and_then(|cache| cache.dac(channel)) {
+            Some(code) => code,
+            None => dac.read_dac(channel)?,
+        };
+        Ok(Ramp::new(channel, from, to, speed, profile))
+    }
+
+    /// The channel of the ramp
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// The code the device holds at the end of the ramp
+    pub fn target(&self) -> u16 {
+        self.to
+    }
+
+    /// Number of ticks of the ramp
+    pub fn ticks(&self) -> u32 {
+        self.steps
+    }
+
+    /// Whether the target has been reached
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.steps
+    }
+
+    /// Write the code of the next tick and update the output, returning whether the target
+    /// has been reached. Ticks that don't change the code aren't sent. The code is written as
+    /// it is, without applying the calibration.
+    /// Call this at the tick rate, e.g. from a timer interrupt.
+    pub fn poll<I2C, R, E, LDAC, CLR>(
+        &mut self,
+        dac: &mut DACx578<I2C, R, LDAC, CLR>,
+    ) -> Result<bool, Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        R: Resolution,
+    {
+        let last = self.last;
+        if let Some(code) = self.next() {
+            if last != Some(code) {
+                dac.write_and_update_uncalibrated(self.channel, code)?;
+            }
+        }
+        Ok(self.is_finished())
+    }
+
+    /// Run the ramp to its target, waiting `tick_us` between ticks
+    pub fn run<I2C, R, E, LDAC, CLR>(
+        &mut self,
+        dac: &mut DACx578<I2C, R, LDAC, CLR>,
+        delay: &mut impl DelayNs,
+        tick_us: u32,
+    ) -> Result<(), Error<E>>
+    where
+        I2C: I2c<Error = E>,
+        R: Resolution,
+    {
+        while !self.poll(dac)? {
+            delay.delay_us(tick_us);
+        }
+        Ok(())
+    }
+}
+
+impl Ramp {
+    /// Code of the tick, rounded to the nearest code
+    fn code(&self, step: u32) -> u16 {
+        let distance = self.from.abs_diff(self.to) as u128;
+        let (step, steps) = (step as u128, self.steps as u128);
+        // Progress as the fraction numerator / denominator
+        let (numerator, denominator) = match self.profile {
+            Profile::Linear => (step, steps),
+            Profile::SCurve => (
+                3 * step * step * steps - 2 * step * step * step,
+                steps * steps * steps,
+            ),
+        };
+        let delta = ((2 * distance * numerator + denominator) / (2 * denominator)) as u16;
+        if self.to >= self.from {
+            self.from + delta
+        } else {
+            self.from - delta
+        }
+    }
+}
+
+impl Iterator for Ramp {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if self.is_finished() {
+            return None;
+        }
+        let previous = self.last.unwrap_or(self.from);
+        let mut code = self.code(self.step + 1);
+        if let Some(rate) = self.rate {
+            code = code.clamp(previous.saturating_sub(rate), previous.saturating_add(rate));
+        }
+        // A clamped last tick is repeated until the target is reached
+        if self.step + 1 < self.steps || code == self.to {
+            self.step += 1;
+        }
+        self.last = Some(code);
+        self.last
+    }
+}
@@ -0,0 +1,305 @@
+//! Software model of the DACx578 for host-side testing.
+//!
+//! The [`Simulator`] implements the embedded-hal [`I2c`] trait (and the embedded-hal-async
+//! one with the `async` feature) and decodes every command the device supports, including the
+//! general call commands. Unlike a mock it tracks the device state, so application logic can be
+//! tested against the semantics of the real device.
+//!
+//! Clones of a simulator share the bus and devices, so one clone can be handed to the driver
+//! while another one is used to inspect the device state:
+//! ```
+//! use dac5578::sim::Simulator;
+//! use dac5578::*;
+//!
+//! let sim = Simulator::new();
+//! sim.add_device::<Bits8>(Address::PinLow);
+//! let mut dac = DAC5578::new(sim.clone(), Address::PinLow);
+//!
+//! dac.write(Channel::A, 10).unwrap();
+//! dac.write_and_update_all(Channel::B, 20).unwrap();
+//! let device = sim.device(Address::PinLow).unwrap();
+//! assert_eq!(device.dac(Channel::A), Some(10));
+//! assert_eq!(device.dac(Channel::B), Some(20));
+//! assert_eq!(dac.read_input(Channel::B).unwrap(), 20);
+//!
+//! dac.reset_all().unwrap();
+//! assert_eq!(sim.device(Address::PinLow).unwrap().dac(Channel::A), Some(0));
+//! ```
+//!
+//! Configuration registers and High-Speed mode are modelled as well and devices that are not attached don't
+//! acknowledge their address:
+//! ```
+//! # use dac5578::sim::Simulator;
+//! # use dac5578::*;
+//! use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+//! let sim = Simulator::new();
+//! sim.add_device::<Bits12>(Address::PinFloat);
+//! let mut dac = DAC7578::new(sim.clone(), Address::PinFloat);
+//!
+//! dac.power_down([Channel::G, Channel::H], PowerDownMode::HighImpedance).unwrap();
+//! dac.set_clear_code(ClearCode::FullScale).unwrap();
+//! dac.set_ldac_mask([Channel::A]).unwrap();
+//! assert_eq!(dac.read_clear_code().unwrap(), ClearCode::FullScale);
+//! assert_eq!(dac.read_ldac_mask().unwrap(), ChannelSet::from(Channel::A));
+//! let device = sim.device(Address::PinFloat).unwrap();
+//! assert_eq!(device.power(Channel::H), Some(PowerState::Down(PowerDownMode::HighImpedance)));
+//! assert_eq!(device.power(Channel::A), Some(PowerState::Up));
+//! assert_eq!(device.power(Channel::All), None);
+//!
+//! dac.wake_up_all().unwrap();
+//! assert_eq!(sim.device(Address::PinFloat).unwrap().power(Channel::H), Some(PowerState::Up));
+//!
+//! dac.enter_high_speed().unwrap();
+//! assert!(sim.device(Address::PinFloat).unwrap().is_high_speed());
+//! dac.reset(ResetMode::MaintainHighSpeed).unwrap();
+//! assert!(sim.device(Address::PinFloat).unwrap().is_high_speed());
+//! dac.leave_high_speed().unwrap();
+//! assert!(!sim.device(Address::PinFloat).unwrap().is_high_speed());
+//!
+//! let mut missing = DAC7578::new(sim, Address::PinLow);
+//! assert_eq!(
+//!     missing.write(Channel::A, 1),
+//!     Err(Error::I2c(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)))
+//! );
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+use crate::{
+    command, Address, Channel, ChannelSet, ClearCode, PowerDownMode, PowerState, Resolution,
+};
+
+/// State of a simulated device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    address: u8,
+    bits: u8,
+    input: [u16; 8],
+    dac: [u16; 8],
+    power_down: [Option<PowerDownMode>; 8],
+    clear_code: ClearCode,
+    ldac_mask: u8,
+    high_speed: bool,
+    pointer: u8,
+}
+
+impl Device {
+    fn new(address: u8, bits: u8) -> Self {
+        Device {
+            address,
+            bits,
+            input: [0; 8],
+            dac: [0; 8],
+            power_down: [None; 8],
+            clear_code: ClearCode::ZeroScale,
+            ldac_mask: 0,
+            high_speed: false,
+            pointer: 0,
+        }
+    }
+
+    /// Code held by the channel's DAC input register, `None` for [`Channel::All`]
+    pub fn input(&self, channel: Channel) -> Option<u16> {
+        Some(self.input[channel.index()?])
+    }
+
+    /// Code held by the channel's DAC register, i.e. the code driving the output.
+    /// `None` for [`Channel::All`].
+    pub fn dac(&self, channel: Channel) -> Option<u16> {
+        Some(self.dac[channel.index()?])
+    }
+
+    /// Power state of the channel, `None` for [`Channel::All`]
+    pub fn power(&self, channel: Channel) -> Option<PowerState> {
+        Some(match self.power_down[channel.index()?] {
+            Some(mode) => PowerState::Down(mode),
+            None => PowerState::Up,
+        })
+    }
+
+    /// Code the outputs are cleared to when the CLR pin is asserted
+    pub fn clear_code(&self) -> ClearCode {
+        self.clear_code
+    }
+
+    /// LDAC register, i.e. the channels that ignore the hardware LDAC pin
+    pub fn ldac_mask(&self) -> ChannelSet {
+        ChannelSet::from_bits(self.ldac_mask)
+    }
+
+    /// Whether the device was put into High-Speed mode by a software reset
+    pub fn is_high_speed(&self) -> bool {
+        self.high_speed
+    }
+
+    /// Reset the device to its power-on state
+    fn reset(&mut self, high_speed: bool) {
+        *self = Device {
+            high_speed,
+            ..Device::new(self.address, self.bits)
+        };
+    }
+
+    /// Execute a three byte command
+    fn execute(&mut self, bytes: &[u8]) {
+        let command = bytes[0] & 0xf0;
+        let access = bytes[0] & 0x0f;
+        let value = u16::from_be_bytes([bytes[1], bytes[2]]);
+        let code = value >> (16 - self.bits);
+        let channels: &[usize] = match access {
+            0..=7 => &[0, 1, 2, 3, 4, 5, 6, 7][access as usize..access as usize + 1],
+            0xf => &[0, 1, 2, 3, 4, 5, 6, 7],
+            _ => &[],
+        };
+
+        match command {
+            0x00 => channels.iter().for_each(|&i| self.input[i] = code),
+            0x10 => channels.iter().for_each(|&i| self.dac[i] = self.input[i]),
+            0x20 => {
+                channels.iter().for_each(|&i| self.input[i] = code);
+                self.dac = self.input;
+            }
+            0x30 => channels.iter().for_each(|&i| {
+                self.input[i] = code;
+                self.dac[i] = code;
+            }),
+            0x40 => {
+                let mode = match value >> 13 & 0b11 {
+                    0b01 => Some(PowerDownMode::Pulldown1K),
+                    0b10 => Some(PowerDownMode::Pulldown100K),
+                    0b11 => Some(PowerDownMode::HighImpedance),
+                    _ => None,
+                };
+                let mask = (value >> 5) as u8;
+                for (i, power_down) in self.power_down.iter_mut().enumerate() {
+                    if mask & 1 << i != 0 {
+                        *power_down = mode;
+                    }
+                }
+            }
+            0x50 => self.clear_code = ClearCode::from_bits(bytes[2] >> 4),
+            0x60 => self.ldac_mask = bytes[1],
+            0x70 => match bytes[1] & 0b11 {
+                0b00 => self.reset(false),
+                0b01 => self.reset(true),
+                0b10 => self.reset(self.high_speed),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Read the register selected by the last command byte.
+    /// The power down register reads as zero.
+    fn read(&self, buffer: &mut [u8]) {
+        let access = (self.pointer & 0x0f) as usize;
+        let value = match self.pointer & 0xf0 {
+            0x00 if access < 8 => self.input[access] << (16 - self.bits),
+            0x10 if access < 8 => self.dac[access] << (16 - self.bits),
+            0x50 => (self.clear_code as u16) << 4,
+            0x60 => (self.ldac_mask as u16) << 8,
+            _ => 0,
+        };
+        for (byte, value) in buffer.iter_mut().zip(value.to_be_bytes().iter().cycle()) {
+            *byte = *value;
+        }
+    }
+}
+
+/// Simulated I2C bus with DACx578 devices attached
+#[derive(Debug, Clone, Default)]
+pub struct Simulator {
+    devices: Arc<Mutex<Vec<Device>>>,
+}
+
+impl Simulator {
+    /// Create a bus without any devices attached
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a device of the given resolution at the address.
+    /// A device already attached at the address is replaced.
+    pub fn add_device<R: Resolution>(&self, address: Address) {
+        let mut devices = self.devices.lock().unwrap();
+        let address = address as u8;
+        devices.retain(|device| device.address != address);
+        devices.push(Device::new(address, R::BITS));
+    }
+
+    /// Snapshot of the state of the device at the address
+    pub fn device(&self, address: Address) -> Option<Device> {
+        let address = address as u8;
+        let devices = self.devices.lock().unwrap();
+        devices
+            .iter()
+            .find(|device| device.address == address)
+            .cloned()
+    }
+
+    /// Handle a general call
+    fn general_call(devices: &mut [Device], bytes: &[u8]) {
+        for device in devices {
+            if bytes == command::WAKE_UP {
+                device.power_down = [None; 8];
+            } else if bytes == command::RESET {
+                device.reset(false);
+            }
+        }
+    }
+}
+
+impl ErrorType for Simulator {
+    type Error = ErrorKind;
+}
+
+impl I2c for Simulator {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut devices = self.devices.lock().unwrap();
+        if address == command::GENERAL_CALL_ADDRESS {
+            for operation in operations {
+                if let Operation::Write(bytes) = operation {
+                    Self::general_call(&mut devices, bytes);
+                }
+            }
+            return Ok(());
+        }
+
+        let device = devices
+            .iter_mut()
+            .find(|device| device.address == address)
+            .ok_or(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))?;
+        for operation in operations {
+            match operation {
+                Operation::Write([pointer]) => device.pointer = *pointer,
+                Operation::Write(bytes) if bytes.len() == 3 => {
+                    device.pointer = bytes[0];
+                    device.execute(bytes);
+                }
+                Operation::Write(_) => {
+                    return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data))
+                }
+                Operation::Read(buffer) => device.read(buffer),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c for Simulator {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        I2c::transaction(self, address, operations)
+    }
+}
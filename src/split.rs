@@ -0,0 +1,175 @@
+//! Channel handles for firmware where different tasks own different outputs.
+//!
+//! [`DACx578::split`] takes a driver held in a cell and returns one [`ChannelHandle`] per
+//! channel, the way HAL GPIO ports split into pins. Each handle can only write and update its own
+//! channel. The handles share the driver through the cell: a [`RefCell`] within a single
+//! execution context or, with the `critical-section` feature, a
+//! `critical_section::Mutex<RefCell<_>>` shared with interrupt handlers. Splitting borrows the
+//! cell exclusively, so while any handle is alive the driver can neither be split again nor be
+//! used directly.
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use core::cell::RefCell;
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x30, 0x80, 0x00]),
+//! #     Transaction::write(0x48, vec![0x01, 0x40, 0x00]),
+//! #     Transaction::write(0x48, vec![0x11, 0x00, 0x00]),
+//! # ]);
+//! let mut dac = RefCell::new(DAC5578::new(i2c, Address::PinLow));
+//! let channels = DAC5578::split(&mut dac);
+//! let (mut heater, mut bias) = (channels.a, channels.b);
+//!
+//! heater.write_and_update(128).unwrap();
+//! bias.write(64).unwrap();
+//! bias.update().unwrap();
+//! # drop((heater, bias));
+//! # dac.into_inner().destroy().done();
+//! ```
+//!
+//! Shared with interrupt handlers through a critical section:
+//! ```
+//! # #[cfg(feature = "critical-section")] {
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use core::cell::RefCell;
+//! use critical_section::Mutex;
+//! # let mut i2c = Mock::new(&[Transaction::write(0x48, vec![0x37, 0xff, 0x00])]);
+//! let mut dac = Mutex::new(RefCell::new(DAC5578::new(i2c, Address::PinLow)));
+//! let mut led = DAC5578::split(&mut dac).h;
+//! led.write_and_update(255).unwrap();
+//! # drop(led);
+//! # dac.into_inner().into_inner().destroy().done();
+//! # }
+//! ```
+//!
+//! A second set of handles can't be created while the first one is alive:
+//! ```compile_fail
+//! # use embedded_hal_mock::eh1::i2c::Mock;
+//! # use dac5578::*;
+//! use core::cell::RefCell;
+//! # let mut i2c = Mock::new(&[]);
+//! let mut dac = RefCell::new(DAC5578::new(i2c, Address::PinLow));
+//! let heater = DAC5578::split(&mut dac).a;
+//! let other = DAC5578::split(&mut dac).a;
+//! # drop((heater, other));
+//! ```
+
+use core::cell::RefCell;
+
+use embedded_hal::i2c::I2c;
+
+use crate::{Channel, DACx578, Error, Resolution};
+
+/// Cell sharing a driver between channel handles
+pub trait Share {
+    /// The shared driver
+    type Driver;
+
+    /// Run the closure with exclusive access to the driver
+    fn lock<T>(&self, f: impl FnOnce(&mut Self::Driver) -> T) -> T;
+}
+
+/// # Panics
+/// Panics when locked again from within [`Share::lock`], e.g. from an interrupt handler.
+impl<D> Share for RefCell<D> {
+    type Driver = D;
+
+    fn lock<T>(&self, f: impl FnOnce(&mut D) -> T) -> T {
+        f(&mut self.borrow_mut())
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl<D> Share for critical_section::Mutex<RefCell<D>> {
+    type Driver = D;
+
+    fn lock<T>(&self, f: impl FnOnce(&mut D) -> T) -> T {
+        critical_section::with(|cs| f(&mut self.borrow_ref_mut(cs)))
+    }
+}
+
+/// Handle to a single channel of a shared driver
+#[derive(Debug)]
+pub struct ChannelHandle<'a, C> {
+    cell: &'a C,
+    channel: Channel,
+}
+
+/// The eight channel handles of a split driver
+#[derive(Debug)]
+pub struct Channels<'a, C> {
+    /// Channel A
+    pub a: ChannelHandle<'a, C>,
+    /// Channel B
+    pub b: ChannelHandle<'a, C>,
+    /// Channel C
+    pub c: ChannelHandle<'a, C>,
+    /// Channel D
+    pub d: ChannelHandle<'a, C>,
+    /// Channel E
+    pub e: ChannelHandle<'a, C>,
+    /// Channel F
+    pub f: ChannelHandle<'a, C>,
+    /// Channel G
+    pub g: ChannelHandle<'a, C>,
+    /// Channel H
+    pub h: ChannelHandle<'a, C>,
+}
+
+impl<I2C, R, E, LDAC, CLR> DACx578<I2C, R, LDAC, CLR>
+where
+    I2C: I2c<Error = E>,
+    R: Resolution,
+{
+    /// Split the driver held by the cell into one handle per channel.
+    /// The cell stays borrowed for as long as any of the handles is alive.
+    pub fn split<C>(cell: &mut C) -> Channels<'_, C>
+    where
+        C: Share<Driver = Self>,
+    {
+        let cell: &C = cell;
+        let handle = |channel| ChannelHandle { cell, channel };
+        Channels {
+            a: handle(Channel::A),
+            b: handle(Channel::B),
+            c: handle(Channel::C),
+            d: handle(Channel::D),
+            e: handle(Channel::E),
+            f: handle(Channel::F),
+            g: handle(Channel::G),
+            h: handle(Channel::H),
+        }
+    }
+}
+
+impl<C, I2C, R, E, LDAC, CLR> ChannelHandle<'_, C>
+where
+    C: Share<Driver = DACx578<I2C, R, LDAC, CLR>>,
+    I2C: I2c<Error = E>,
+    R: Resolution,
+{
+    /// The channel of the handle
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Write to the channel's DAC input register
+    pub fn write(&mut self, code: u16) -> Result<(), Error<E>> {
+        let channel = self.channel;
+        self.cell.lock(|dac| dac.write(channel, code))
+    }
+
+    /// Update the channel's DAC register from its input register
+    pub fn update(&mut self) -> Result<(), Error<E>> {
+        let channel = self.channel;
+        self.cell.lock(|dac| dac.update(channel, 0))
+    }
+
+    /// Write to the channel's DAC input register and update its DAC register
+    pub fn write_and_update(&mut self, code: u16) -> Result<(), Error<E>> {
+        let channel = self.channel;
+        self.cell.lock(|dac| dac.write_and_update(channel, code))
+    }
+}
@@ -0,0 +1,269 @@
+use crate::cache::Cache;
+use crate::calibration::Calibration;
+use crate::command::{self, Commands};
+use crate::{Channel, ChannelSet, ClearCode, CommandType, PowerDownMode, PowerState, Resolution};
+
+/// Target state of the device, applied with [`crate::DACx578::apply`].
+/// Registers left unset are don't-care and keep whatever the device holds.
+///
+/// The driver compares the target with the shadow register cache and only sends the commands
+/// needed to get there. When more than one output changes, the codes are written to the input
+/// registers and latched with a single global update, so all outputs change together. A global
+/// update would also latch codes staged in the input registers of channels without a target,
+/// so if there are any, each output is written and updated on its own instead.
+/// ```
+/// # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+/// # use dac5578::*;
+/// # let mut i2c = Mock::new(&[
+/// #     Transaction::write(0x48, vec![0x70, 0x00, 0x00]),
+/// #     Transaction::write(0x48, vec![0x40, 0x70, 0x00]),
+/// #     Transaction::write(0x48, vec![0x00, 0x0a, 0x00]),
+/// #     Transaction::write(0x48, vec![0x21, 0x14, 0x00]),
+/// #     Transaction::write(0x48, vec![0x50, 0x00, 0x10]),
+/// #     Transaction::write(0x48, vec![0x30, 0x1e, 0x00]),
+/// # ]);
+/// # let mut dac = DAC5578::new(i2c, Address::PinLow);
+/// // The cache knows the power-on state after a reset
+/// dac.enable_cache();
+/// dac.reset(ResetMode::Por).unwrap();
+///
+/// let mut state = DesiredState::new();
+/// state.set_code(Channel::A, 10);
+/// state.set_code(Channel::B, 20);
+/// state.set_power(Channel::H, PowerState::Down(PowerDownMode::HighImpedance));
+/// state.set_clear_code(ClearCode::MidScale);
+/// assert_eq!(dac.apply(&state).unwrap(), 4);
+/// assert_eq!(dac.apply(&state).unwrap(), 0);
+///
+/// state.set_code(Channel::A, 30);
+/// assert_eq!(dac.apply(&state).unwrap(), 1);
+/// # dac.destroy().done();
+/// ```
+///
+/// Channels without a target keep both their output and a code staged in their input register:
+/// ```
+/// # #[cfg(feature = "sim")] {
+/// # use dac5578::*;
+/// use dac5578::sim::Simulator;
+///
+/// let sim = Simulator::new();
+/// sim.add_device::<Bits8>(Address::PinLow);
+/// let mut dac = DAC5578::new(sim.clone(), Address::PinLow);
+/// // Staged, but not latched yet
+/// dac.write(Channel::C, 77).unwrap();
+///
+/// let mut state = DesiredState::new();
+/// state.set_code(Channel::A, 10);
+/// state.set_code(Channel::B, 20);
+/// dac.apply(&state).unwrap();
+///
+/// let device = sim.device(Address::PinLow).unwrap();
+/// assert_eq!(device.dac(Channel::A), Some(10));
+/// assert_eq!(device.dac(Channel::B), Some(20));
+/// assert_eq!(device.input(Channel::C), Some(77));
+/// assert_eq!(device.dac(Channel::C), Some(0));
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DesiredState {
+    codes: [Option<u16>; 8],
+    power: [Option<PowerState>; 8],
+    clear_code: Option<ClearCode>,
+    ldac_mask: Option<ChannelSet>,
+}
+
+impl DesiredState {
+    /// Create a state without any targets
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Target code of the channel, `None` if don't-care or for [`Channel::All`]
+    pub fn code(&self, channel: Channel) -> Option<u16> {
+        self.codes[channel.index()?]
+    }
+
+    /// Set the code the channel should output. [`Channel::All`] sets all channels.
+    pub fn set_code(&mut self, channel: Channel, code: u16) {
+        ChannelSet::from(channel)
+            .iter()
+            .for_each(|c| self.codes[c as usize] = Some(code));
+    }
+
+    /// Target power state of the channel, `None` if don't-care or for [`Channel::All`]
+    pub fn power(&self, channel: Channel) -> Option<PowerState> {
+        self.power[channel.index()?]
+    }
+
+    /// Set the power state of the given channels
+    pub fn set_power<C: Into<ChannelSet>>(&mut self, channels: C, state: PowerState) {
+        channels
+            .into()
+            .iter()
+            .for_each(|c| self.power[c as usize] = Some(state));
+    }
+
+    /// Target clear code, `None` if don't-care
+    pub fn clear_code(&self) -> Option<ClearCode> {
+        self.clear_code
+    }
+
+    /// Set the code the outputs are cleared to when the CLR pin is asserted
+    pub fn set_clear_code(&mut self, code: ClearCode) {
+        self.clear_code = Some(code);
+    }
+
+    /// Target set of channels that ignore the hardware LDAC pin, `None` if don't-care
+    pub fn ldac_mask(&self) -> Option<ChannelSet> {
+        self.ldac_mask
+    }
+
+    /// Set which channels ignore the hardware LDAC pin
+    pub fn set_ldac_mask<C: Into<ChannelSet>>(&mut self, channels: C) {
+        self.ldac_mask = Some(channels.into());
+    }
+
+    /// First target code above the part's maximum
+    pub(crate) fn out_of_range<R: Resolution>(&self) -> Option<u16> {
+        self.codes
+            .iter()
+            .flatten()
+            .copied()
+            .find(|&code| code > R::MAX_CODE)
+    }
+
+    /// Channels whose input and DAC registers have to be read back before planning.
+    /// A global update latches every channel, so unless the outputs that aren't targeted
+    /// are known to match their input registers, the update could change them.
+    pub(crate) fn unknown<R: Resolution>(
+        &self,
+        cache: &Cache,
+        calibration: Option<&Calibration>,
+    ) -> ChannelSet {
+        let targets = self.targets::<R>(calibration);
+        if changed(cache, &targets).len() < 2 {
+            return ChannelSet::EMPTY;
+        }
+        Channel::iter()
+            .filter(|&c| targets[c as usize].is_none())
+            .filter(|&c| cache.input(c).is_none() || cache.dac(c).is_none())
+            .collect()
+    }
+
+    /// Commands moving the device from the cached state to the target state.
+    /// Channels being powered down are powered down before their code changes, channels being
+    /// powered up are powered up after it.
+    pub(crate) fn plan<R: Resolution>(
+        &self,
+        cache: &Cache,
+        calibration: Option<&Calibration>,
+    ) -> Commands {
+        let mut commands = Commands::new();
+        let power = |state: PowerState| {
+            Channel::iter()
+                .filter(|&c| self.power[c as usize] == Some(state) && cache.power(c) != Some(state))
+                .collect::<ChannelSet>()
+        };
+
+        for mode in [
+            PowerDownMode::Pulldown1K,
+            PowerDownMode::Pulldown100K,
+            PowerDownMode::HighImpedance,
+        ] {
+            let channels = power(PowerState::Down(mode));
+            if !channels.is_empty() {
+                commands.push(command::power(channels, mode as u8));
+            }
+        }
+
+        self.plan_codes::<R>(cache, calibration, &mut commands);
+
+        let channels = power(PowerState::Up);
+        if !channels.is_empty() {
+            commands.push(command::power(channels, command::POWER_UP));
+        }
+        if let Some(code) = self
+            .clear_code
+            .filter(|&code| cache.clear_code() != Some(code))
+        {
+            commands.push(command::clear_code(code));
+        }
+        if let Some(mask) = self
+            .ldac_mask
+            .filter(|&mask| cache.ldac_mask() != Some(mask))
+        {
+            commands.push(command::ldac(mask));
+        }
+        commands
+    }
+
+    /// Code writes for the channels whose output has to change.
+    /// A single change is written and updated directly. Several changes are written to the
+    /// input registers and latched by the last write with a global update, unless a channel
+    /// without a target may hold a code staged in its input register; the global update would
+    /// latch it, so each change is written and updated directly then.
+    fn plan_codes<R: Resolution>(
+        &self,
+        cache: &Cache,
+        calibration: Option<&Calibration>,
+        commands: &mut Commands,
+    ) {
+        let targets = self.targets::<R>(calibration);
+        let changed = changed(cache, &targets);
+        let last = match changed.iter().last() {
+            Some(last) => last,
+            None => return,
+        };
+        let latched = |c: Channel| {
+            targets[c as usize].filter(|&code| changed.contains(c) || cache.input(c) != Some(code))
+        };
+        let staged = Channel::iter().any(|c| {
+            targets[c as usize].is_none()
+                && (cache.input(c).is_none() || cache.input(c) != cache.dac(c))
+        });
+
+        if changed.len() == 1 || staged {
+            for channel in changed.iter() {
+                let code = targets[channel as usize].unwrap_or_default();
+                commands.push(command::encode::<R>(
+                    CommandType::WriteToChannelAndUpdate,
+                    channel as u8,
+                    code,
+                ));
+            }
+            return;
+        }
+        for channel in Channel::iter().filter(|&c| c != last) {
+            if let Some(code) = latched(channel) {
+                commands.push(command::encode::<R>(
+                    CommandType::WriteToChannel,
+                    channel as u8,
+                    code,
+                ));
+            }
+        }
+        commands.push(command::encode::<R>(
+            CommandType::WriteToChannelAndUpdateAll,
+            last as u8,
+            targets[last as usize].unwrap_or_default(),
+        ));
+    }
+
+    /// Target codes as sent to the device, i.e. with the calibration applied
+    fn targets<R: Resolution>(&self, calibration: Option<&Calibration>) -> [Option<u16>; 8] {
+        let mut targets = self.codes;
+        if let Some(calibration) = calibration {
+            for (index, target) in targets.iter_mut().enumerate() {
+                *target = target.map(|code| calibration.correct_index(index, code, R::MAX_CODE));
+            }
+        }
+        targets
+    }
+}
+
+/// Targeted channels whose output isn't known to hold the target code
+fn changed(cache: &Cache, targets: &[Option<u16>; 8]) -> ChannelSet {
+    Channel::iter()
+        .filter(|&c| matches!(targets[c as usize], Some(code) if cache.dac(c) != Some(code)))
+        .collect()
+}
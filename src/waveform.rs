@@ -0,0 +1,267 @@
+//! Direct digital synthesis of waveforms on the DAC channels.
+//!
+//! A [`Generator`] keeps a 32 bit phase accumulator per channel and advances it at a tick rate
+//! chosen by the caller, e.g. a timer interrupt. Every channel has its own [`Waveform`],
+//! frequency, amplitude, offset and phase, which can be set before or after its waveform.
+//! Amplitude and offset are codes of the part's resolution and the output is clamped to its
+//! range. On every [`Generator::tick`] the next codes are either written and updated channel by
+//! channel, or staged in the input registers and latched together with a global update (see
+//! [`UpdateMode`]).
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+//! # use dac5578::*;
+//! use dac5578::waveform::{Generator, Waveform};
+//! # let mut i2c = Mock::new(&[
+//! #     Transaction::write(0x48, vec![0x00, 0x00, 0x00]),
+//! #     Transaction::write(0x48, vec![0x21, 0xff, 0x00]),
+//! #     Transaction::write(0x48, vec![0x00, 0x40, 0x00]),
+//! #     Transaction::write(0x48, vec![0x21, 0x00, 0x00]),
+//! # ]);
+//! # let mut dac = DAC5578::new(i2c, Address::PinLow);
+//! // Ticks at 4 Hz, so a 1 Hz waveform takes four samples per period
+//! let mut generator = Generator::new(4);
+//! generator.set_waveform(Channel::A, Waveform::Sawtooth);
+//! generator.set_frequency(Channel::A, 1000);
+//! // Square wave a quarter period ahead
+//! generator.set_phase(Channel::B, 0x4000);
+//! generator.set_waveform(Channel::B, Waveform::Square);
+//!
+//! generator.tick(&mut dac).unwrap();
+//! generator.tick(&mut dac).unwrap();
+//! assert_eq!(generator.next_codes().1[..2], [128, 0]);
+//! # dac.destroy().done();
+//! ```
+
+use core::marker::PhantomData;
+
+use embedded_hal::i2c::I2c;
+
+use crate::{Channel, ChannelSet, DACx578, Error, Resolution};
+
+/// Quarter period of a sine in Q15, sampled at 65 points
+const SINE: [i16; 65] = [
+    0, 804, 1608, 2410, 3212, 4011, 4808, 5602, 6393, 7179, 7962, 8739, 9512, 10278, 11039, 11793,
+    12539, 13279, 14010, 14732, 15446, 16151, 16846, 17530, 18204, 18868, 19519, 20159, 20787,
+    21403, 22005, 22594, 23170, 23731, 24279, 24811, 25329, 25832, 26319, 26790, 27245, 27683,
+    28105, 28510, 28898, 29268, 29621, 29956, 30273, 30571, 30852, 31113, 31356, 31580, 31785,
+    31971, 32137, 32285, 32412, 32521, 32609, 32678, 32728, 32757, 32767,
+];
+
+/// Shape of a waveform over one period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform<'a> {
+    /// Sine starting at its center value
+    Sine,
+    /// Triangle rising from its minimum
+    Triangle,
+    /// Sawtooth rising from its minimum
+    Sawtooth,
+    /// Square wave, at its maximum for the first half period
+    Square,
+    /// Arbitrary samples spread evenly over one period, from -32767 to 32767
+    Table(&'a [i16]),
+}
+
+impl Waveform<'_> {
+    /// Value of the waveform at the phase, a fraction of the period in 1/2^32, from -32768 to 32767
+    pub fn sample(&self, phase: u32) -> i32 {
+        let half = (phase >> 16) as i32;
+        match self {
+            Waveform::Sine => sine(phase),
+            Waveform::Triangle if half < 0x8000 => 2 * half - 0x8000,
+            Waveform::Triangle => 0x17fff - 2 * half,
+            Waveform::Sawtooth => half - 0x8000,
+            Waveform::Square if half < 0x8000 => 0x7fff,
+            Waveform::Square => -0x8000,
+            Waveform::Table([]) => 0,
+            Waveform::Table(table) => {
+                table[((phase as u64 * table.len() as u64) >> 32) as usize] as i32
+            }
+        }
+    }
+}
+
+/// How the codes of a tick are sent to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// Write and update each channel on its own
+    Individual,
+    /// Write all channels to their input registers and update them together with the last write
+    Synchronized,
+}
+
+/// Oscillator of a single channel, generating only while it has a waveform
+#[derive(Debug, Clone, Copy)]
+struct Oscillator<'a> {
+    waveform: Option<Waveform<'a>>,
+    phase: u32,
+    increment: u32,
+    phase_offset: u32,
+    amplitude: u16,
+    offset: u16,
+}
+
+/// Waveform generator for the eight channels of a part with resolution `R`
+#[derive(Debug)]
+pub struct Generator<'a, R> {
+    tick_hz: u32,
+    mode: UpdateMode,
+    oscillators: [Oscillator<'a>; 8],
+    resolution: PhantomData<R>,
+}
+
+impl<'a, R: Resolution> Generator<'a, R> {
+    /// Create a generator ticking at the given rate, without any waveforms.
+    ///
+    /// # Panics
+    /// Panics if the tick rate is zero.
+    pub fn new(tick_hz: u32) -> Self {
+        assert!(tick_hz > 0, "tick rate must not be zero");
+        let mut generator = Generator {
+            tick_hz,
+            mode: UpdateMode::Synchronized,
+            oscillators: [Oscillator {
+                waveform: None,
+                phase: 0,
+                increment: 0,
+                phase_offset: 0,
+                amplitude: 1 << (R::BITS - 1),
+                offset: 1 << (R::BITS - 1),
+            }; 8],
+            resolution: PhantomData,
+        };
+        generator.set_frequency(Channel::All, 1000);
+        generator
+    }
+
+    /// Rate at which [`Self::tick`] is called, in Hz
+    pub fn tick_hz(&self) -> u32 {
+        self.tick_hz
+    }
+
+    /// Select how the codes of a tick are sent, [`UpdateMode::Synchronized`] by default
+    pub fn set_update_mode(&mut self, mode: UpdateMode) {
+        self.mode = mode;
+    }
+
+    /// Output the waveform on the channel. [`Channel::All`] sets all channels.
+    /// Unless set before, a channel runs at 1 Hz and zero phase, centered at mid-scale with an
+    /// amplitude of half-scale, so it spans the full range of the part.
+    pub fn set_waveform(&mut self, channel: Channel, waveform: Waveform<'a>) {
+        self.update(channel, |oscillator| oscillator.waveform = Some(waveform));
+    }
+
+    /// Stop generating a waveform on the channel, leaving its output at the last code.
+    /// The channel keeps its frequency, amplitude, offset and phase.
+    /// [`Channel::All`] stops all channels.
+    pub fn remove_waveform(&mut self, channel: Channel) {
+        self.update(channel, |oscillator| oscillator.waveform = None);
+    }
+
+    /// Set the frequency of the channel's waveform in mHz.
+    /// Frequencies above half the tick rate alias.
+    pub fn set_frequency(&mut self, channel: Channel, millihertz: u32) {
+        let increment = self.increment(millihertz);
+        self.update(channel, |oscillator| oscillator.increment = increment);
+    }
+
+    /// Set the amplitude of the channel's waveform, i.e. the peak deviation from its offset in
+    /// codes of the part
+    pub fn set_amplitude(&mut self, channel: Channel, amplitude: u16) {
+        self.update(channel, |oscillator| oscillator.amplitude = amplitude);
+    }
+
+    /// Set the center code of the channel's waveform
+    pub fn set_offset(&mut self, channel: Channel, offset: u16) {
+        self.update(channel, |oscillator| oscillator.offset = offset);
+    }
+
+    /// Set the phase of the channel's waveform as a fraction of the period in 1/65536
+    pub fn set_phase(&mut self, channel: Channel, phase: u16) {
+        self.update(channel, |oscillator| {
+            oscillator.phase_offset = (phase as u32) << 16
+        });
+    }
+
+    /// Restart the waveforms of all channels at their phase
+    pub fn restart(&mut self) {
+        for oscillator in self.oscillators.iter_mut() {
+            oscillator.phase = 0;
+        }
+    }
+
+    /// Codes of the channels with a waveform for the current tick, advancing their phase.
+    /// Codes of other channels are zero.
+    pub fn next_codes(&mut self) -> (ChannelSet, [u16; 8]) {
+        let mut channels = ChannelSet::EMPTY;
+        let mut codes = [0; 8];
+        for (channel, oscillator) in Channel::iter().zip(self.oscillators.iter_mut()) {
+            if let Some(waveform) = oscillator.waveform {
+                let phase = oscillator.phase.wrapping_add(oscillator.phase_offset);
+                let sample = waveform.sample(phase);
+                let value =
+                    oscillator.offset as i32 + oscillator.amplitude as i32 * sample / 0x7fff;
+                codes[channel as usize] = value.clamp(0, R::MAX_CODE as i32) as u16;
+                channels.insert(channel);
+                oscillator.phase = oscillator.phase.wrapping_add(oscillator.increment);
+            }
+        }
+        (channels, codes)
+    }
+
+    /// Send the codes of the current tick to the device and advance the waveforms.
+    /// Call this at the tick rate.
+    pub fn tick<I2C, E, LDAC, CLR>(
+        &mut self,
+        dac: &mut DACx578<I2C, R, LDAC, CLR>,
+    ) -> Result<(), Error<E>>
+    where
+        I2C: I2c<Error = E>,
+    {
+        let (channels, codes) = self.next_codes();
+        match self.mode {
+            UpdateMode::Individual => {
+                for channel in channels.iter() {
+                    dac.write_and_update(channel, codes[channel as usize])?;
+                }
+                Ok(())
+            }
+            UpdateMode::Synchronized => dac.write_and_update_channels(channels, &codes),
+        }
+    }
+
+    /// Phase increment per tick for the frequency
+    fn increment(&self, millihertz: u32) -> u32 {
+        (((millihertz as u64) << 32) / (self.tick_hz as u64 * 1000)) as u32
+    }
+
+    /// Modify the oscillators of the channels
+    fn update(&mut self, channel: Channel, mut f: impl FnMut(&mut Oscillator<'a>)) {
+        for c in ChannelSet::from(channel).iter() {
+            f(&mut self.oscillators[c as usize]);
+        }
+    }
+}
+
+/// Sine of the phase in Q15, interpolated from the quarter period table
+fn sine(phase: u32) -> i32 {
+    let quarter = phase & 0x3fff_ffff;
+    let position = match phase >> 30 {
+        0 | 2 => quarter,
+        _ => 0x4000_0000 - quarter,
+    } >> 8;
+    let index = (position >> 16) as usize;
+    let value = match SINE.get(index + 1) {
+        Some(&next) => {
+            let fraction = (position & 0xffff) as i32;
+            SINE[index] as i32 + (((next - SINE[index]) as i32 * fraction) >> 16)
+        }
+        None => SINE[index] as i32,
+    };
+    if phase >> 31 == 0 {
+        value
+    } else {
+        -value
+    }
+}